@@ -9,32 +9,42 @@ pub const BLOCK_BITS: usize = BLOCK_SIZE * 8;
 // bitmap块，物理大小BLOCK_SIZE的u64数组
 type BitmapBlock = [u64; BLOCK_SIZE / 8];
 
-// Bitmap块集合，只需要记录第一个块的id和块数量
+// Bitmap块集合，只需要记录第一个块的id、块数量和可分配的数量
 pub struct Bitmap {
     first_block: u32,
     blocks: u32,
+    bits: u32, // 可分配的数量，bitmap块中超出该数量的位不会被分配
 }
 
 impl Bitmap {
-    pub fn new(first_block: u32, blocks: u32) -> Self {
-        return Self {first_block, blocks};
+    pub fn new(first_block: u32, blocks: u32, bits: u32) -> Self {
+        return Self {first_block, blocks, bits: bits.min(blocks * BLOCK_BITS as u32)};
     }
     // 分配一个块，返回block id
+    // 扫描时只读，只有找到空闲位的bitmap块会被修改，已满的块不会进入当前事务
     pub fn alloc_block(&self, block_device: Arc<dyn BlockDevice>) -> Option<u32> {
         for block in 0..self.blocks {
             let pos = block + self.first_block;
             let cache = get_block_cache(pos as usize, Arc::clone(&block_device));
             let mut locked = cache.lock();
-            let bitmap_block: &mut BitmapBlock = locked.get_mut(0);
-            let res = bitmap_block.iter()
-            .enumerate()
-            .find(|(_, m)| {**m != u64::MAX})       // 找到还有0的u64数字
-            .map(|(idx, bits64)| {(idx, (*bits64).trailing_ones())}); // 找到第一个为0的二进制位的位置
+            let res = locked.read(0, |bitmap_block: &BitmapBlock| {
+                bitmap_block.iter()
+                .enumerate()
+                .find(|(_, m)| {**m != u64::MAX})       // 找到还有0的u64数字
+                .map(|(idx, bits64)| {(idx, (*bits64).trailing_ones())}) // 找到第一个为0的二进制位的位置
+            });
             if let Some((idx, inner_pos)) = res {
-                // 将该位置设置1
-                bitmap_block[idx] = bitmap_block[idx] | (1u64 << inner_pos);
                 // 分配的block的顺序序号
-                return Some(block * BLOCK_BITS as u32 + idx as u32 * 64 + inner_pos as u32);
+                let seq = block * BLOCK_BITS as u32 + idx as u32 * 64 + inner_pos;
+                // 第一个空闲位已经超出可分配范围，没有空闲块
+                if seq >= self.bits {
+                    return None;
+                }
+                // 将该位置设置1
+                locked.modify(0, |bitmap_block: &mut BitmapBlock| {
+                    bitmap_block[idx] |= 1u64 << inner_pos;
+                });
+                return Some(seq);
             }
         }
         return None;
@@ -46,15 +56,65 @@ impl Bitmap {
         let mut locked = cache.lock();
         locked.modify(0, |bitmap_block: &mut BitmapBlock|{
             // 将二进制位设置为0
-            bitmap_block[idx as usize] &= !((1u64<<u64_offset) - 1);
+            bitmap_block[idx as usize] &= !(1u64 << u64_offset);
         })
     }
+
+    // bitmap能分配的块总数
+    pub fn bits(&self) -> u32 {
+        return self.bits;
+    }
+
+    // 统计已分配的块数量，需要扫描所有bitmap块
+    pub fn count_allocated(&self, block_device: Arc<dyn BlockDevice>) -> u32 {
+        let mut count = 0;
+        for block in 0..self.blocks {
+            let pos = block + self.first_block;
+            count += get_block_cache(pos as usize, Arc::clone(&block_device))
+            .lock()
+            .read(0, |bitmap_block: &BitmapBlock| {
+                let mut count = 0;
+                for (idx, bits64) in bitmap_block.iter().enumerate() {
+                    let start = block * BLOCK_BITS as u32 + idx as u32 * 64;
+                    if start >= self.bits {
+                        break;
+                    }
+                    // 忽略超出可分配范围的位
+                    let valid = (self.bits - start).min(64);
+                    let mask = if valid == 64 {u64::MAX} else {(1u64 << valid) - 1};
+                    count += (*bits64 & mask).count_ones();
+                }
+                count
+            });
+        }
+        return count;
+    }
+
+    // 序号为seq的块是否已分配
+    pub fn is_allocated(&self, seq: u32, block_device: Arc<dyn BlockDevice>) -> bool {
+        let (block, idx, u64_offset) = decompose_bits(seq);
+        return get_block_cache(block as usize + self.first_block as usize, Arc::clone(&block_device))
+        .lock()
+        .read(0, |bitmap_block: &BitmapBlock| {
+            bitmap_block[idx as usize] & (1u64 << u64_offset) != 0
+        });
+    }
+
+    // 将序号为seq的块标记为已分配
+    pub fn set_allocated(&self, seq: u32, block_device: Arc<dyn BlockDevice>) {
+        let (block, idx, u64_offset) = decompose_bits(seq);
+        get_block_cache(block as usize + self.first_block as usize, Arc::clone(&block_device))
+        .lock()
+        .modify(0, |bitmap_block: &mut BitmapBlock| {
+            bitmap_block[idx as usize] |= 1u64 << u64_offset;
+        });
+    }
 }
 
 // 从bit序号计算block序号, idx, u64 offset
 fn decompose_bits(mut bits: u32) -> (u32, u32, u32) {
     let block = bits / BLOCK_BITS as u32;
-    bits = bits % BLOCK_SIZE as u32;
+    bits = bits % BLOCK_BITS as u32;
     return (block as u32, bits as u32 / 64, bits as u32 % 64);
 }
 
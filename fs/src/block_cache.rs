@@ -1,11 +1,14 @@
 use alloc::sync::Arc;
 use super::block_device::BlockDevice;
-use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::hash::{BuildHasherDefault, Hasher};
+use hashbrown::HashMap;
 use spin::mutex::Mutex;
 use lazy_static::lazy_static;
 
 // 一个磁盘块的大小，4KiB
 pub const BLOCK_SIZE: usize = 4096;
+// 块缓存默认容量
 pub const BLOCK_CACHE_SIZE: usize = 16;
 
 // 一个磁盘块缓存项
@@ -17,20 +20,81 @@ pub struct BlockCache {
     pub block_device: Arc<dyn BlockDevice> // 块设备引用
 }
 
-// 块缓存管理器
+// 块缓存的键：(块设备id, 块ID)，不同块设备上相同块ID的缓存互不影响
+type CacheKey = (usize, usize);
+
+// 空链表指针
+const NIL: usize = usize::MAX;
+
+// 块缓存键的哈希函数，键只有两个整数，不需要防碰撞的哈希
+#[derive(Default)]
+struct KeyHasher(u64);
+
+// LRU链表节点，节点保存在数组中，用下标互相链接
+struct LruNode {
+    key: CacheKey,
+    cache: Option<Arc<Mutex<BlockCache>>>, // 互斥的共享所有权，None表示空闲节点
+    prev: usize,
+    next: usize,
+}
+
+// 块缓存管理器，哈希表O(1)查找缓存块，LRU链表决定淘汰顺序
 pub struct BlockCacheManager {
-    caches: VecDeque<(usize, Arc<Mutex<BlockCache>>)>, // 互斥的共享所有权
+    capacity: usize,                                              // 缓存容量
+    map: HashMap<CacheKey, usize, BuildHasherDefault<KeyHasher>>, // 键到节点下标的映射
+    nodes: Vec<LruNode>,                                          // 链表节点
+    free: Vec<usize>,                                             // 空闲节点下标
+    head: usize,                                                  // 最近使用的节点
+    tail: usize,                                                  // 最久未使用的节点
+    transactions: Vec<usize>,                                     // 正在进行事务的块设备
 }
 
 // 懒加载 块缓存管理器 单例，Mutex包装保证互斥访问
 lazy_static!{
-    pub static ref BLOCK_CACHE_MANAGER: Mutex<BlockCacheManager> = Mutex::new(BlockCacheManager::new());
+    pub static ref BLOCK_CACHE_MANAGER: Mutex<BlockCacheManager> = Mutex::new(BlockCacheManager::new(BLOCK_CACHE_SIZE));
 }
 
 pub fn get_block_cache(block_id: usize, block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<BlockCache>> {
     return BLOCK_CACHE_MANAGER.lock().get_block_cache(block_id, block_device);
 }
 
+// 设置全局块缓存的容量，应在挂载文件系统之前调用
+pub fn set_block_cache_capacity(capacity: usize) {
+    BLOCK_CACHE_MANAGER.lock().set_capacity(capacity);
+}
+
+// 将所有缓存块写回块设备
+pub fn sync_all() {
+    BLOCK_CACHE_MANAGER.lock().sync_all();
+}
+
+// 将块设备上所有被修改的缓存块写回，并刷新块设备
+pub fn sync_device(block_device: &Arc<dyn BlockDevice>) {
+    BLOCK_CACHE_MANAGER.lock().sync_device(block_device);
+    block_device.flush();
+}
+
+// 在块设备上开始一个事务，事务结束前该设备被修改的块不会因淘汰而写回
+pub fn begin_transaction(block_device: &Arc<dyn BlockDevice>) {
+    BLOCK_CACHE_MANAGER.lock().begin_transaction(block_device);
+}
+
+// 结束块设备上的事务，返回事务期间被修改的缓存块
+pub fn end_transaction(block_device: &Arc<dyn BlockDevice>) -> Vec<Arc<Mutex<BlockCache>>> {
+    return BLOCK_CACHE_MANAGER.lock().end_transaction(block_device);
+}
+
+// 当前事务中块设备被修改的缓存块数量
+pub fn transaction_blocks(block_device: &Arc<dyn BlockDevice>) -> usize {
+    return BLOCK_CACHE_MANAGER.lock().transaction_blocks(block_device);
+}
+
+// 块设备id，使用块设备对象的地址区分不同的块设备
+// 缓存项持有块设备的引用，所以缓存存在期间该地址不会被其他块设备复用
+fn device_id(block_device: &Arc<dyn BlockDevice>) -> usize {
+    return Arc::as_ptr(block_device) as *const () as usize;
+}
+
 
 impl BlockCache {
     // 创建新的缓存块，从块设备读取数据缓存
@@ -111,28 +175,185 @@ impl Drop for BlockCache {
 }
 
 impl BlockCacheManager {
-    pub fn new() -> Self {
-        return Self {caches: VecDeque::new()};
+    // 创建容量为capacity个块的缓存管理器
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "block cache capacity must be positive");
+        return Self {
+            capacity: capacity,
+            map: HashMap::default(),
+            nodes: Vec::new(),
+            free: Vec::new(),
+            head: NIL,
+            tail: NIL,
+            transactions: Vec::new(),
+        };
+    }
+
+    pub fn capacity(&self) -> usize {
+        return self.capacity;
+    }
+
+    // 当前缓存的块数，所有块都被引用时可能暂时超过容量
+    pub fn len(&self) -> usize {
+        return self.map.len();
+    }
+
+    // 缓存中是否没有块
+    pub fn is_empty(&self) -> bool {
+        return self.map.is_empty();
+    }
+
+    // 修改缓存容量，缩小容量时尽量淘汰多余的块
+    pub fn set_capacity(&mut self, capacity: usize) {
+        assert!(capacity > 0, "block cache capacity must be positive");
+        self.capacity = capacity;
+        self.shrink(capacity);
     }
 
     pub fn get_block_cache(&mut self, block_id: usize, block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<BlockCache>> {
-        // 从缓存找到block_id对应的块缓存
-        if let Some(pair) = self.caches.iter().find(|pair|{pair.0 == block_id}) {
-            return Arc::clone(&pair.1);
-        }
-        // 达到缓存上限，弹出一个块
-        if self.caches.len() == BLOCK_CACHE_SIZE {
-            // 弹出引用计数为1，即只被manager持有引用的块
-            if let Some((idx, _)) = self.caches.iter().enumerate().find(|(_, pair)| {Arc::strong_count(&pair.1) == 1}) {
-                self.caches.remove(idx);
-            }else {
-                // 没有空闲的块，缓存耗尽
-                panic!("block cache full, cant push new block cache");
-            }
+        let key = (device_id(&block_device), block_id);
+        // 从缓存找到块设备上block_id对应的块缓存，并移动到链表头部
+        if let Some(&idx) = self.map.get(&key) {
+            self.detach(idx);
+            self.push_front(idx);
+            return Arc::clone(self.nodes[idx].cache.as_ref().unwrap());
         }
+        // 达到缓存上限，为新的块腾出位置
+        self.shrink(self.capacity - 1);
         // 创建缓存块
         let block_cache = Arc::new(Mutex::new(BlockCache::new(block_id, block_device)));
-        self.caches.push_back((block_id, Arc::clone(&block_cache)));
+        let node = LruNode {
+            key: key,
+            cache: Some(Arc::clone(&block_cache)),
+            prev: NIL,
+            next: NIL,
+        };
+        let idx = match self.free.pop() {
+            Some(idx) => {
+                self.nodes[idx] = node;
+                idx
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        self.push_front(idx);
+        self.map.insert(key, idx);
         return block_cache;
     }
-}
\ No newline at end of file
+
+    // 同步所有被修改的缓存块
+    pub fn sync_all(&self) {
+        for cache in self.nodes.iter().filter_map(|node| node.cache.as_ref()) {
+            cache.lock().sync();
+        }
+    }
+
+    // 同步某个块设备上所有被修改的缓存块
+    pub fn sync_device(&self, block_device: &Arc<dyn BlockDevice>) {
+        let id = device_id(block_device);
+        for node in self.nodes.iter().filter(|node| {node.key.0 == id}) {
+            if let Some(cache) = node.cache.as_ref() {
+                cache.lock().sync();
+            }
+        }
+    }
+
+    pub fn begin_transaction(&mut self, block_device: &Arc<dyn BlockDevice>) {
+        let id = device_id(block_device);
+        assert!(!self.transactions.contains(&id), "nested transaction on block device");
+        self.transactions.push(id);
+    }
+
+    pub fn end_transaction(&mut self, block_device: &Arc<dyn BlockDevice>) -> Vec<Arc<Mutex<BlockCache>>> {
+        let id = device_id(block_device);
+        self.transactions.retain(|device| {*device != id});
+        return self.nodes.iter()
+        .filter(|node| {node.key.0 == id})
+        .filter_map(|node| {node.cache.as_ref()})
+        .filter(|cache| {cache.lock().modified})
+        .map(|cache| {Arc::clone(cache)})
+        .collect();
+    }
+
+    pub fn transaction_blocks(&self, block_device: &Arc<dyn BlockDevice>) -> usize {
+        let id = device_id(block_device);
+        return self.nodes.iter()
+        .filter(|node| {node.key.0 == id})
+        .filter_map(|node| {node.cache.as_ref()})
+        .filter(|cache| {cache.lock().modified})
+        .count();
+    }
+
+    // 缓存块能否被淘汰：只被manager持有引用，且不是事务中被修改的块
+    fn evictable(&self, idx: usize) -> bool {
+        let cache = self.nodes[idx].cache.as_ref().unwrap();
+        if Arc::strong_count(cache) != 1 {
+            return false;
+        }
+        return !self.transactions.contains(&self.nodes[idx].key.0) || !cache.lock().modified;
+    }
+
+    // 从最久未使用的一端淘汰块，直到缓存块数不超过limit
+    // 没有可以淘汰的块时暂时超出容量，不再panic
+    fn shrink(&mut self, limit: usize) {
+        let mut idx = self.tail;
+        while self.map.len() > limit && idx != NIL {
+            let prev = self.nodes[idx].prev;
+            if self.evictable(idx) {
+                self.detach(idx);
+                self.map.remove(&self.nodes[idx].key);
+                // 丢弃缓存块时会自动写回块设备
+                self.nodes[idx].cache = None;
+                self.free.push(idx);
+            }
+            idx = prev;
+        }
+    }
+
+    // 将节点从LRU链表中取下
+    fn detach(&mut self, idx: usize) {
+        let (prev, next) = (self.nodes[idx].prev, self.nodes[idx].next);
+        if prev == NIL {
+            self.head = next;
+        } else {
+            self.nodes[prev].next = next;
+        }
+        if next == NIL {
+            self.tail = prev;
+        } else {
+            self.nodes[next].prev = prev;
+        }
+        self.nodes[idx].prev = NIL;
+        self.nodes[idx].next = NIL;
+    }
+
+    // 将节点放到LRU链表头部，即最近使用的位置
+    fn push_front(&mut self, idx: usize) {
+        self.nodes[idx].prev = NIL;
+        self.nodes[idx].next = self.head;
+        if self.head == NIL {
+            self.tail = idx;
+        } else {
+            self.nodes[self.head].prev = idx;
+        }
+        self.head = idx;
+    }
+}
+
+impl Hasher for KeyHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_usize(*byte as usize);
+        }
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.0 = (self.0.rotate_left(5) ^ n as u64).wrapping_mul(0x517cc1b727220a95);
+    }
+
+    fn finish(&self) -> u64 {
+        return self.0;
+    }
+}
@@ -5,4 +5,45 @@ pub trait BlockDevice: Send + Sync + Any {
     fn read_block(&self, block_id: usize, buf: &mut [u8]);
     // 写入一个块
     fn write_block(&self, block_id: usize, buf: &[u8]);
-}
\ No newline at end of file
+    // 将设备自身的写缓冲刷到持久存储，写操作本身同步完成的设备无需实现
+    fn flush(&self) {}
+}
+
+#[cfg(feature = "std")]
+pub use self::block_file::BlockFile;
+
+// 宿主机上以普通文件作为块设备，用于打包和检查文件系统镜像
+#[cfg(feature = "std")]
+mod block_file {
+    use super::BlockDevice;
+    use crate::block_cache::BLOCK_SIZE;
+    use std::fs::File;
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::sync::Mutex;
+
+    pub struct BlockFile(Mutex<File>);
+
+    impl BlockFile {
+        pub fn new(file: File) -> Self {
+            return Self(Mutex::new(file));
+        }
+    }
+
+    impl BlockDevice for BlockFile {
+        fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+            let mut file = self.0.lock().unwrap();
+            file.seek(SeekFrom::Start((block_id * BLOCK_SIZE) as u64)).expect("file seek failed");
+            assert_eq!(file.read(buf).unwrap(), BLOCK_SIZE, "NOT a complete block");
+        }
+
+        fn write_block(&self, block_id: usize, buf: &[u8]) {
+            let mut file = self.0.lock().unwrap();
+            file.seek(SeekFrom::Start((block_id * BLOCK_SIZE) as u64)).expect("file seek failed");
+            assert_eq!(file.write(buf).unwrap(), BLOCK_SIZE, "NOT a complete block");
+        }
+
+        fn flush(&self) {
+            self.0.lock().unwrap().sync_data().expect("file sync failed");
+        }
+    }
+}
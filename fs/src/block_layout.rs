@@ -1,7 +1,12 @@
+use super::inode::INODES_PER_BLOCK;
+
 const FS_MAGIC: u32 = 0xf3fc;
+// 超级块中空闲inode和数据块计数有效的标记，没有该标记的旧镜像在打开时重新统计
+const FREE_COUNT_MAGIC: u32 = 0x46524545;
 
 // 超级块，管理磁盘中的所有块
-// 磁盘块布局：| super | inode bitmaps | inodes | data bitmaps | data blks |
+// 磁盘块布局：| super | journal | inode bitmaps | inodes | data bitmaps | data blks |
+// 没有日志区域的旧镜像journal_blocks为0
 #[repr(C)]
 pub struct SuperBlock {
     magic: u32,               // 超级块验证magic num
@@ -9,20 +14,45 @@ pub struct SuperBlock {
     pub inode_blocks: u32,        // inode块数量
     pub data_bitmap_blocks: u32,  // 数据bitmap块数量
     pub data_blocks: u32,         // 数据块数量
+    pub journal_blocks: u32,      // 日志区域块数，日志区域紧跟在超级块之后
+    free_count_magic: u32,        // 空闲计数有效标记
+    pub free_inodes: u32,         // 空闲inode数量，分配和回收inode时更新
+    pub free_data_blocks: u32,    // 空闲数据块数量，分配和回收数据块时更新
 }
 
 impl SuperBlock {
-    pub fn new(inode_bitmaps: u32, inodes: u32, data_bitmaps: u32, data_blocks: u32) -> Self {
+    pub fn new(journal_blocks: u32, inode_bitmaps: u32, inodes: u32, data_bitmaps: u32, data_blocks: u32) -> Self {
         return Self { magic: FS_MAGIC, inode_bitmap_blocks: inode_bitmaps,
-            inode_blocks: inodes, data_bitmap_blocks: data_bitmaps, data_blocks: data_blocks };
+            inode_blocks: inodes, data_bitmap_blocks: data_bitmaps, data_blocks: data_blocks,
+            journal_blocks: journal_blocks, free_count_magic: FREE_COUNT_MAGIC,
+            free_inodes: inodes * INODES_PER_BLOCK, free_data_blocks: data_blocks };
     }
 
-    pub fn init(&mut self, inode_bitmaps: u32, inodes: u32, data_bitmaps: u32, data_blocks: u32) {
+    pub fn init(&mut self, journal_blocks: u32, inode_bitmaps: u32, inodes: u32, data_bitmaps: u32, data_blocks: u32) {
          self.magic = FS_MAGIC;
          self.inode_bitmap_blocks = inode_bitmaps;
          self.inode_blocks = inodes;
          self.data_bitmap_blocks = data_bitmaps;
          self.data_blocks = data_blocks;
+         self.journal_blocks = journal_blocks;
+         self.set_free_counts(inodes * INODES_PER_BLOCK, data_blocks);
+    }
+
+    // 设置空闲inode和数据块计数，并标记计数有效
+    pub fn set_free_counts(&mut self, free_inodes: u32, free_data_blocks: u32) {
+        self.free_count_magic = FREE_COUNT_MAGIC;
+        self.free_inodes = free_inodes;
+        self.free_data_blocks = free_data_blocks;
+    }
+
+    // 空闲计数是否有效
+    pub fn has_free_counts(&self) -> bool {
+        return self.free_count_magic == FREE_COUNT_MAGIC;
+    }
+
+    // inode bitmap区域的起始块号
+    pub fn inode_bitmap_start(&self) -> u32 {
+        return 1 + self.journal_blocks;
     }
     pub fn is_valid(&self) -> bool {
         return self.magic == FS_MAGIC;
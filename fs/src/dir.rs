@@ -1,21 +1,52 @@
+use super::error::{FsError, FsResult};
+use super::block_cache::BLOCK_SIZE;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
 
-pub const NAME_LIMIT: usize = 27;
+// 文件名的最大长度
+pub const NAME_LIMIT: usize = 255;
+// 一个目录项能直接容纳的文件名长度
+pub const SHORT_NAME_LIMIT: usize = 27;
+// 目录项槽的大小
 pub const DIR_SIZE: u32 = 32;
+// 目录项第一个槽中inode编号的偏移，短文件名和长文件名相同
+pub const INODE_ID_OFFSET: u32 = SHORT_NAME_LIMIT as u32 + 1;
+// 长文件名目录项首字节的标记，合法的UTF-8字符串不会出现该字节
+const LONG_NAME_MARK: u8 = 0xff;
+// 长文件名目录项中能容纳的文件名长度，首字节为标记，第二个字节为文件名长度
+const LONG_HEAD_NAME_LIMIT: usize = SHORT_NAME_LIMIT - 1;
+// 散列目录中一个桶占用一个数据块
+pub const BUCKET_SLOTS: u32 = BLOCK_SIZE as u32 / DIR_SIZE;
+// 散列目录的最大桶数，桶数达到上限后文件名所在的桶仍然放不下时目录退化为线性目录
+pub const MAX_BUCKETS: u32 = 1024;
 
 // 一个目录项，大小32字节
 // 记录目录项名字 和 对应的inode id
+// 文件名超过27字节时使用长文件名扩展：
+// 第一个槽的名字依次为 0xff、文件名长度、文件名的前26字节，后面连续的槽全部用于存放文件名剩余的部分
 #[repr(C)]
 pub struct DirEntry {
-    name: [u8; NAME_LIMIT + 1], // 名字，C字符串，末尾\0
-    inode_id: u32,              // 目录项对应的inode
+    name: [u8; SHORT_NAME_LIMIT + 1], // 名字，C字符串，末尾\0
+    inode_id: u32,                    // 目录项对应的inode
+}
+
+// 从目录数据中解析出的一个目录项，可能占用多个连续的槽
+pub struct DirRecord {
+    pub slot: u32,            // 第一个槽的序号
+    pub slots: u32,           // 占用的槽数量
+    pub name: Option<String>, // 文件名，目录项损坏时为None
+    pub inode_id: u32,
 }
 
 impl DirEntry {
     pub fn empty() -> Self {
-        return Self {name: [0u8; NAME_LIMIT + 1], inode_id: 0};
+        return Self {name: [0u8; SHORT_NAME_LIMIT + 1], inode_id: 0};
     }
 
+    // 创建文件名不超过27字节的目录项，更长的文件名使用encode_entry
     pub fn new(name: &str, inode_id: u32) -> Self {
+        assert!(name.len() <= SHORT_NAME_LIMIT);
         let mut entry = Self::empty();
         entry.name[..name.len()].copy_from_slice(name.as_bytes());
         entry.inode_id = inode_id;
@@ -32,15 +63,153 @@ impl DirEntry {
         unsafe {return core::slice::from_raw_parts_mut(ptr, DIR_SIZE as usize);}
     }
 
-    pub fn name(&self) -> &str {
-        let len = self.name.iter().enumerate()
-        .find(|(_, b)| {**b == 0})
-        .map(|(idx, _)| {idx})
-        .unwrap();
-        return core::str::from_utf8(&self.name[..len]).unwrap();
+    // 空目录项，文件被删除后留下的空位
+    pub fn is_empty(&self) -> bool {
+        return self.name[0] == 0;
     }
-    
+
     pub fn inode_id(&self) -> u32 {
         return self.inode_id;
     }
-}
\ No newline at end of file
+}
+
+// 文件名的散列值，使用FNV-1a，磁盘上散列目录的布局依赖该值，不能修改
+pub fn name_hash(name: &str) -> u32 {
+    let mut hash: u32 = 0x811c9dc5;
+    for b in name.bytes() {
+        hash ^= b as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    return hash;
+}
+
+// 散列目录中文件名所在的桶，使用线性散列，桶数不需要是2的幂
+// 桶数为 2^level + split 时，前split个桶已经分裂，按散列值的低level+1位分桶，其余的桶按低level位分桶
+// 桶数为2的幂时所有桶都按低level位分桶
+pub fn name_bucket(name: &str, buckets: u32) -> u32 {
+    let hash = name_hash(name);
+    let low = level_buckets(buckets);
+    let bucket = hash & (low - 1);
+    if bucket < buckets - low {
+        return hash & ((low << 1).wrapping_sub(1));
+    }
+    return bucket;
+}
+
+// 桶数从buckets增加到buckets + 1时被分裂的桶，新桶的序号为buckets
+// 反过来，桶数从buckets + 1减少到buckets时，最后一个桶合并到该桶中
+pub fn split_source(buckets: u32) -> u32 {
+    return buckets - level_buckets(buckets);
+}
+
+// 桶数从buckets增加到buckets + 1时，被分裂的是否为文件名所在的桶，空目录分配第一个桶时不是
+pub fn splits_name_bucket(name: &str, buckets: u32) -> bool {
+    return buckets > 0 && split_source(buckets) == name_bucket(name, buckets);
+}
+
+// 不超过buckets的最大的2的幂，buckets不能为0
+fn level_buckets(buckets: u32) -> u32 {
+    return 1 << (31 - buckets.leading_zeros());
+}
+
+// 在records所在的total个槽中找到连续slots个空槽，返回第一个槽的序号
+// records需按槽序号排列，即parse_entries的结果
+pub fn find_free_slots(records: &[DirRecord], total: u32, slots: u32) -> Option<u32> {
+    let mut start = 0;
+    for record in records {
+        if record.slot >= start + slots {
+            break;
+        }
+        start = record.slot + record.slots;
+    }
+    if start + slots <= total {
+        return Some(start);
+    }
+    return None;
+}
+
+// 文件名长度为len的目录项占用的槽数量
+pub fn slots_for_name(len: usize) -> u32 {
+    if len <= SHORT_NAME_LIMIT {
+        return 1;
+    }
+    return 1 + (len - LONG_HEAD_NAME_LIMIT).div_ceil(DIR_SIZE as usize) as u32;
+}
+
+// 将文件名和inode编号编码为目录项槽数据，文件名超过255字节时返回NameTooLong
+pub fn encode_entry(name: &str, inode_id: u32) -> FsResult<Vec<u8>> {
+    if name.is_empty() {
+        return Err(FsError::InvalidName);
+    }
+    if name.len() > NAME_LIMIT {
+        return Err(FsError::NameTooLong);
+    }
+    if name.len() <= SHORT_NAME_LIMIT {
+        return Ok(Vec::from(DirEntry::new(name, inode_id).to_bytes()));
+    }
+    let bytes = name.as_bytes();
+    let mut data = vec![0u8; slots_for_name(bytes.len()) as usize * DIR_SIZE as usize];
+    let mut head = DirEntry::empty();
+    head.name[0] = LONG_NAME_MARK;
+    head.name[1] = bytes.len() as u8;
+    head.name[2..].copy_from_slice(&bytes[..LONG_HEAD_NAME_LIMIT]);
+    head.inode_id = inode_id;
+    data[..DIR_SIZE as usize].copy_from_slice(head.to_bytes());
+    data[DIR_SIZE as usize..DIR_SIZE as usize + bytes.len() - LONG_HEAD_NAME_LIMIT].copy_from_slice(&bytes[LONG_HEAD_NAME_LIMIT..]);
+    return Ok(data);
+}
+
+// 解析目录数据中的所有目录项，跳过空的槽
+// 损坏的槽（短文件名没有结尾\0、不是UTF-8、长文件名超出目录范围）作为单独一个槽返回，名字为None
+pub fn parse_entries(data: &[u8]) -> Vec<DirRecord> {
+    return parse_entries_partial(data, false).0;
+}
+
+// 解析目录的一部分数据，more表示data之后还有目录数据
+// 末尾的长文件名目录项超出data时不解析，返回解析出的目录项和已解析的字节数，剩余部分应与之后的数据一起解析
+pub fn parse_entries_partial(data: &[u8], more: bool) -> (Vec<DirRecord>, usize) {
+    let mut records = Vec::new();
+    let total = (data.len() / DIR_SIZE as usize) as u32;
+    let mut slot = 0;
+    while slot < total {
+        let offset = (slot * DIR_SIZE) as usize;
+        let mut entry = DirEntry::empty();
+        entry.to_bytes_mut().copy_from_slice(&data[offset..offset + DIR_SIZE as usize]);
+        if entry.is_empty() {
+            slot += 1;
+            continue;
+        }
+        let len = entry.name[1] as usize;
+        if more && entry.name[0] == LONG_NAME_MARK && len > SHORT_NAME_LIMIT && slot + slots_for_name(len) > total {
+            break;
+        }
+        let (slots, name) = if entry.name[0] == LONG_NAME_MARK {
+            parse_long_name(&entry, &data[offset..])
+        } else {
+            (1, parse_short_name(&entry))
+        };
+        records.push(DirRecord { slot, slots, name, inode_id: entry.inode_id });
+        slot += slots;
+    }
+    return (records, (slot.min(total) * DIR_SIZE) as usize);
+}
+
+fn parse_short_name(entry: &DirEntry) -> Option<String> {
+    let len = entry.name.iter().position(|b| {*b == 0})?;
+    return core::str::from_utf8(&entry.name[..len]).ok().map(String::from);
+}
+
+// 解析长文件名目录项，data从第一个槽开始，返回 (占用的槽数量, 文件名)
+fn parse_long_name(head: &DirEntry, data: &[u8]) -> (u32, Option<String>) {
+    let len = head.name[1] as usize;
+    let slots = slots_for_name(len);
+    if len <= SHORT_NAME_LIMIT || slots as usize * DIR_SIZE as usize > data.len() {
+        return (1, None);
+    }
+    let mut bytes = Vec::from(&head.name[2..]);
+    bytes.extend_from_slice(&data[DIR_SIZE as usize..DIR_SIZE as usize + len - LONG_HEAD_NAME_LIMIT]);
+    return match String::from_utf8(bytes) {
+        Ok(name) => (slots, Some(name)),
+        Err(_) => (1, None),
+    };
+}
@@ -0,0 +1,42 @@
+use core::fmt;
+
+// 文件系统操作的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    NoSpace,      // 没有空闲的inode或数据块
+    NotDir,       // 需要目录的操作作用在了非目录上
+    IsDir,        // 不能作用于目录的操作作用在了目录上
+    NotFound,     // 文件或目录不存在
+    Exists,       // 文件或目录已经存在
+    NotEmpty,     // 删除非空目录
+    InvalidName,  // 文件名为空、包含'/'或者是"."和".."
+    NameTooLong,  // 文件名超过255字节，或符号链接的目标路径过长
+    NotLink,      // 需要符号链接的操作作用在了其他类型的文件上
+    TooManyLinks, // 解析路径时跟随的符号链接过多，可能存在循环
+    InvalidImage, // 块设备上不是有效的文件系统
+    OutOfRange,   // 读写位置超出文件范围
+    InvalidArgument, // 参数组合无效，如将目录移动到自身的子目录中
+}
+
+pub type FsResult<T> = Result<T, FsError>;
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            FsError::NoSpace => "no space left on device",
+            FsError::NotDir => "not a directory",
+            FsError::IsDir => "is a directory",
+            FsError::NotFound => "no such file or directory",
+            FsError::Exists => "file exists",
+            FsError::NotEmpty => "directory not empty",
+            FsError::InvalidName => "invalid file name",
+            FsError::NameTooLong => "file name too long",
+            FsError::NotLink => "not a symbolic link",
+            FsError::TooManyLinks => "too many levels of symbolic links",
+            FsError::InvalidImage => "invalid file system image",
+            FsError::OutOfRange => "offset out of range",
+            FsError::InvalidArgument => "invalid argument",
+        };
+        return write!(f, "{}", msg);
+    }
+}
@@ -1,30 +1,60 @@
 use super::block_device::BlockDevice;
 use super::bitmap::{Bitmap, BLOCK_BITS};
 use super::block_layout::SuperBlock;
-use super::block_cache::{get_block_cache, BLOCK_SIZE};
-use super::inode::{INODES_PER_BLOCK, DiskINode, INodeType::Directory, INODE_SIZE};
+use super::block_cache::{get_block_cache, sync_device, begin_transaction, end_transaction, transaction_blocks, BLOCK_SIZE};
+use super::journal::{Journal, JOURNAL_BLOCKS};
+use super::inode::{INODES_PER_BLOCK, DiskINode, INodeType::Directory, INODE_SIZE, FLAG_HASHED_DIR};
 use super::vfs::INode;
+use super::dir::{DirEntry, DIR_SIZE};
+use super::error::{FsError, FsResult};
 use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
 use spin::Mutex;
 
+// 文件系统的容量和空闲空间
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StatFs {
+    pub block_size: u32,       // 块大小
+    pub total_inodes: u32,     // inode总数
+    pub free_inodes: u32,      // 空闲inode数量
+    pub total_blocks: u32,     // 数据块总数
+    pub free_blocks: u32,      // 空闲数据块数量
+}
+
+// 时钟回调，返回当前时间，单位秒
+// 由内核或主机工具提供，文件系统不依赖具体的计时器
+pub type Clock = fn() -> u32;
+
 pub struct FileSystem {
     pub block_dev: Arc<dyn BlockDevice>, // 文件系统块设备
     pub inode_bitmap: Bitmap,            // inode分配表
     pub data_bitmap: Bitmap,             // data分配表
     inode_area_start: u32,               // inode区域起始块号
     data_area_start: u32,                // data区域起始块号
+    journal: Option<Journal>,            // 写前日志，旧镜像没有日志区域
+    clock: Option<Clock>,                // 时钟，用于inode的时间戳
 }
 
 impl FileSystem {
-    // 在块设备上创建文件系统
-    pub fn create(block_dev: Arc<dyn BlockDevice>, total_blocks: u32, inode_bitmap_blocks: u32) -> Self {
+    // 在块设备上创建文件系统，块数不足以容纳元数据区域和至少一个数据块时返回NoSpace
+    pub fn create(block_dev: Arc<dyn BlockDevice>, total_blocks: u32, inode_bitmap_blocks: u32) -> FsResult<Self> {
+        if inode_bitmap_blocks == 0 {
+            return Err(FsError::NoSpace);
+        }
         // 因为一个block可以存多个inode，所以inode块总数 = bit总数（inode总数） /  一个块中能容纳的inode数
         let inode_blocks = inode_bitmap_blocks * BLOCK_BITS as u32 / INODES_PER_BLOCK;
-        // 去除超级块、inode块后剩余的交给数据块和数据bitmap
-        let remaining = total_blocks - (inode_blocks + inode_bitmap_blocks + 1);
+        // 元数据区域之后至少需要一个data bitmap块和一个数据块
+        let metadata_blocks = inode_blocks + inode_bitmap_blocks + 1 + JOURNAL_BLOCKS;
+        if total_blocks < metadata_blocks + 2 {
+            return Err(FsError::NoSpace);
+        }
+        // 去除超级块、日志区域、inode块后剩余的交给数据块和数据bitmap
+        let remaining = total_blocks - metadata_blocks;
         // data bitmap块数量 = 剩余块 / （一个bitmap块和若干数据块） 向上取整
-        let data_bitmap_blocks = (remaining + BLOCK_BITS as u32 + 1) / (BLOCK_BITS as u32 + 1);
-        let data_blocks = data_bitmap_blocks * (BLOCK_BITS as u32);
+        let data_bitmap_blocks = (remaining + BLOCK_BITS as u32) / (BLOCK_BITS as u32 + 1);
+        let data_blocks = remaining - data_bitmap_blocks;
 
         // 清空缓存
         for i in 0..(total_blocks-1) {
@@ -38,42 +68,149 @@ impl FileSystem {
         get_block_cache(0, Arc::clone(&block_dev))
         .lock()
         .modify(0, |super_block: &mut SuperBlock| {
-            super_block.init(inode_bitmap_blocks, inode_blocks, data_bitmap_blocks, data_blocks);
+            super_block.init(JOURNAL_BLOCKS, inode_bitmap_blocks, inode_blocks, data_bitmap_blocks, data_blocks);
         });
+        // 先写回清空的块，再初始化日志区域，日志区域之后只直接读写块设备
+        sync_device(&block_dev);
+        let journal = Journal::new(1, JOURNAL_BLOCKS, Arc::clone(&block_dev));
+        journal.init();
 
-        return Self{
+        let inode_bitmap_start = 1 + JOURNAL_BLOCKS;
+        return Ok(Self{
             block_dev: block_dev,
-            inode_bitmap: Bitmap::new(1, inode_bitmap_blocks),
-            data_bitmap: Bitmap::new(1 + inode_bitmap_blocks + inode_blocks, data_bitmap_blocks),
-            inode_area_start: 1 + inode_bitmap_blocks,
-            data_area_start: 1 + inode_bitmap_blocks + inode_blocks + data_bitmap_blocks,
-        };
+            inode_bitmap: Bitmap::new(inode_bitmap_start, inode_bitmap_blocks, inode_blocks * INODES_PER_BLOCK),
+            data_bitmap: Bitmap::new(inode_bitmap_start + inode_bitmap_blocks + inode_blocks, data_bitmap_blocks, data_blocks),
+            inode_area_start: inode_bitmap_start + inode_bitmap_blocks,
+            data_area_start: inode_bitmap_start + inode_bitmap_blocks + inode_blocks + data_bitmap_blocks,
+            journal: Some(journal),
+            clock: None,
+        });
     }
 
-    // 从块设备上打开一个文件系统
-    pub fn open(block_dev: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
+    // 从块设备上打开一个文件系统，重放日志中已提交的事务
+    // 超级块无效时返回InvalidImage
+    pub fn open(block_dev: Arc<dyn BlockDevice>) -> FsResult<Arc<Mutex<Self>>> {
+        let journal_blocks = get_block_cache(0, Arc::clone(&block_dev))
+        .lock()
+        .read(0, |super_block: &SuperBlock| {
+            if !super_block.is_valid() {
+                return Err(FsError::InvalidImage);
+            }
+            return Ok(super_block.journal_blocks);
+        })?;
+        let journal = if journal_blocks > 0 {
+            let journal = Journal::new(1, journal_blocks, Arc::clone(&block_dev));
+            journal.replay();
+            Some(journal)
+        } else {
+            None
+        };
         // 读取超级块，闭包处理后返回文件系统实例
-        return get_block_cache(0, Arc::clone(&block_dev))
+        let fs = get_block_cache(0, Arc::clone(&block_dev))
         .lock()
         .read(0, |super_block: &SuperBlock| {
-            if super_block.is_valid() {
-                // 根据超级块的信息，获取文件系统数据块、inode块位置
-                let inode_blocks = super_block.inode_blocks;
-                let inode_bitmap_blocks = super_block.inode_bitmap_blocks;
-                // 获取bitmap区域
-                let inode_bitmap = Bitmap::new(1, inode_bitmap_blocks);
-                let data_bitmap = Bitmap::new(1 + inode_bitmap_blocks + inode_blocks, super_block.data_bitmap_blocks);
-                let fs =  Self {
-                    block_dev: block_dev,
-                    inode_bitmap: inode_bitmap,
-                    inode_area_start: 1 + inode_bitmap_blocks,
-                    data_bitmap: data_bitmap,
-                    data_area_start: 1 + inode_bitmap_blocks + inode_blocks + super_block.data_bitmap_blocks,
-                };
-                return Arc::new(Mutex::new(fs));
-            }else {
-                panic!("invalid file system super block");
+            // 根据超级块的信息，获取文件系统数据块、inode块位置
+            let inode_blocks = super_block.inode_blocks;
+            let inode_bitmap_blocks = super_block.inode_bitmap_blocks;
+            let inode_bitmap_start = super_block.inode_bitmap_start();
+            // 获取bitmap区域
+            let inode_bitmap = Bitmap::new(inode_bitmap_start, inode_bitmap_blocks, inode_blocks * INODES_PER_BLOCK);
+            let data_bitmap = Bitmap::new(inode_bitmap_start + inode_bitmap_blocks + inode_blocks, super_block.data_bitmap_blocks, super_block.data_blocks);
+            let fs =  Self {
+                block_dev: block_dev,
+                inode_bitmap: inode_bitmap,
+                inode_area_start: inode_bitmap_start + inode_bitmap_blocks,
+                data_bitmap: data_bitmap,
+                data_area_start: inode_bitmap_start + inode_bitmap_blocks + inode_blocks + super_block.data_bitmap_blocks,
+                journal: journal,
+                clock: None,
+            };
+            return fs;
+        });
+        // 旧镜像的超级块中没有空闲计数，扫描bitmap统计一次
+        let has_free_counts = fs.read_super_block(|super_block| {super_block.has_free_counts()});
+        if !has_free_counts {
+            fs.begin();
+            fs.recount_free();
+            fs.commit();
+        }
+        return Ok(Arc::new(Mutex::new(fs)));
+    }
+
+    // 设置时钟，之后创建和修改的inode使用该时钟记录时间戳
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = Some(clock);
+    }
+
+    // 当前时间，没有设置时钟时为0
+    pub fn now(&self) -> u32 {
+        return self.clock.map_or(0, |clock| {clock()});
+    }
+
+    // 开始一个事务，事务期间修改的块在提交前不会写回块设备
+    // 调用者需持有文件系统的锁，保证同一时间只有一个事务
+    pub fn begin(&self) {
+        begin_transaction(&self.block_dev);
+    }
+
+    // 提交事务，通过日志将事务期间修改的块原子地写回块设备
+    // 没有日志区域的旧镜像，修改的块仍留在缓存中等待写回
+    pub fn commit(&self) {
+        let dirty = end_transaction(&self.block_dev);
+        if let Some(journal) = &self.journal {
+            journal.commit(dirty);
+        }
+    }
+
+    // 事务修改的块超过日志容量的一半时，提交当前事务并开始新的事务
+    // 用于fsck修复等可以分多步完成的长时间修改，每一步结束时的状态都应可以重新检查和修复
+    pub fn checkpoint(&self) {
+        if let Some(journal) = &self.journal {
+            if transaction_blocks(&self.block_dev) * 2 >= journal.capacity() {
+                self.commit();
+                self.begin();
             }
+        }
+    }
+
+    fn read_super_block<V>(&self, f: impl FnOnce(&SuperBlock) -> V) -> V {
+        return get_block_cache(0, Arc::clone(&self.block_dev))
+        .lock()
+        .read(0, f);
+    }
+
+    fn modify_super_block<V>(&self, f: impl FnOnce(&mut SuperBlock) -> V) -> V {
+        return get_block_cache(0, Arc::clone(&self.block_dev))
+        .lock()
+        .modify(0, f);
+    }
+
+    // 文件系统的容量和空闲空间，空闲数量直接读取超级块中的计数
+    pub fn statfs(&self) -> StatFs {
+        let (free_inodes, free_blocks) = self.read_super_block(|super_block| {
+            (super_block.free_inodes, super_block.free_data_blocks)
+        });
+        return StatFs {
+            block_size: BLOCK_SIZE as u32,
+            total_inodes: self.inode_bitmap.bits(),
+            free_inodes,
+            total_blocks: self.data_bitmap.bits(),
+            free_blocks,
+        };
+    }
+
+    // 扫描inode和data bitmap，返回实际的 (空闲inode数量, 空闲数据块数量)
+    pub fn count_free(&self) -> (u32, u32) {
+        let used_inodes = self.inode_bitmap.count_allocated(Arc::clone(&self.block_dev));
+        let used_blocks = self.data_bitmap.count_allocated(Arc::clone(&self.block_dev));
+        return (self.inode_bitmap.bits() - used_inodes, self.data_bitmap.bits() - used_blocks);
+    }
+
+    // 按bitmap重新统计超级块中的空闲计数
+    pub fn recount_free(&self) {
+        let (free_inodes, free_blocks) = self.count_free();
+        self.modify_super_block(|super_block| {
+            super_block.set_free_counts(free_inodes, free_blocks);
         });
     }
 
@@ -84,40 +221,91 @@ impl FileSystem {
         return (inode_block, inner_inode_id, inner_inode_id * INODE_SIZE);
     }
 
+    // 根据inode所在的全局块号和块内偏移计算inode编号
+    pub fn get_inode_id(&self, block_id: u32, block_offset: u32) -> u32 {
+        return (block_id - self.inode_area_start) * INODES_PER_BLOCK + block_offset / INODE_SIZE;
+    }
+
     // 获取一个数据块的全局块号
     pub fn get_data_block_id(&self, data_id: u32) -> u32 {
         return self.data_area_start + data_id;
     }
-    // 从inode bitmap分配一个inode块，返回inode区域局部块号
-    pub fn alloc_inode(&mut self) -> u32 {
-        return self.inode_bitmap.alloc_block(Arc::clone(&self.block_dev)).unwrap();
+
+    // 全局块号是否位于data区域内
+    pub fn is_data_block(&self, block_id: u32) -> bool {
+        return block_id >= self.data_area_start && block_id - self.data_area_start < self.data_bitmap.bits();
+    }
+    // 从inode bitmap分配一个inode，返回inode编号，没有空闲inode时返回NoSpace
+    pub fn alloc_inode(&mut self) -> FsResult<u32> {
+        let inode_id = self.inode_bitmap.alloc_block(Arc::clone(&self.block_dev)).ok_or(FsError::NoSpace)?;
+        self.modify_super_block(|super_block| {super_block.free_inodes = super_block.free_inodes.saturating_sub(1)});
+        return Ok(inode_id);
+    }
+
+    // 回收一个inode，参数为inode编号
+    pub fn dealloc_inode(&mut self, inode_id: u32) {
+        self.inode_bitmap.dealloc(inode_id, Arc::clone(&self.block_dev));
+        self.modify_super_block(|super_block| {super_block.free_inodes += 1});
     }
 
     // 分配data块，获取全局块号
-    pub fn alloc_data_block(&mut self) -> u32 {
-        return self.data_bitmap.alloc_block(Arc::clone(&self.block_dev)).unwrap() + self.data_area_start;
+    // 在分配时清空块数据，回收时不再修改块内容，避免回收大文件时产生大量需要写日志的块
+    // 没有空闲数据块时返回NoSpace
+    pub fn alloc_data_block(&mut self) -> FsResult<u32> {
+        let block_id = self.data_bitmap.alloc_block(Arc::clone(&self.block_dev)).ok_or(FsError::NoSpace)? + self.data_area_start;
+        get_block_cache(block_id as usize, Arc::clone(&self.block_dev))
+        .lock()
+        .clear();
+        self.modify_super_block(|super_block| {super_block.free_data_blocks = super_block.free_data_blocks.saturating_sub(1)});
+        return Ok(block_id);
     }
 
     // 回收一个data块
     pub fn dealloc_data_block(&mut self, block_id: u32) {
-        let block_cache = get_block_cache(block_id as usize, Arc::clone(&self.block_dev));
-        let mut locked_cache = block_cache.lock();
-        // 清空缓存数据
-        locked_cache.clear();
         // bitmap回收data_block
         self.data_bitmap.dealloc(block_id - self.data_area_start, Arc::clone(&self.block_dev));
+        self.modify_super_block(|super_block| {super_block.free_data_blocks += 1});
     }
 
-    // 创建root目录inode
-    pub fn create_root_inode(&mut self) -> u32 {
+    // 创建root目录inode，root目录的"."和".."都指向自身
+    pub fn create_root_inode(&mut self) -> FsResult<u32> {
+        self.begin();
         let inode_seq = self.alloc_inode();
+        let data_block = self.alloc_data_block();
+        let (inode_seq, data_block) = match (inode_seq, data_block) {
+            (Ok(inode_seq), Ok(data_block)) => (inode_seq, data_block),
+            (inode_seq, data_block) => {
+                // 回收已经分配的部分
+                if let Ok(inode_seq) = inode_seq {
+                    self.dealloc_inode(inode_seq);
+                }
+                if let Ok(data_block) = data_block {
+                    self.dealloc_data_block(data_block);
+                }
+                self.commit();
+                return Err(FsError::NoSpace);
+            }
+        };
         let (block_id, _, block_off) = self.get_inode_block_id(inode_seq);
+        let block_dev = Arc::clone(&self.block_dev);
+        let now = self.now();
         get_block_cache(block_id as usize, Arc::clone(&self.block_dev))
         .lock()
         .modify(block_off as usize, |disk_inode: &mut DiskINode| {
-            disk_inode._type = Directory;
+            disk_inode.init(Directory, now);
+            // root目录是只有一个桶的散列目录，所有目录项都在第一个数据块中
+            disk_inode.flags |= FLAG_HASHED_DIR;
+            disk_inode.increse_size(BLOCK_SIZE as u32, vec![data_block], Vec::new(), Arc::clone(&block_dev));
+            disk_inode.write(0, DirEntry::new(".", inode_seq).to_bytes(), Arc::clone(&block_dev));
+            disk_inode.write(DIR_SIZE, DirEntry::new("..", inode_seq).to_bytes(), Arc::clone(&block_dev));
         });
-        return inode_seq;
+        self.commit();
+        return Ok(inode_seq);
+    }
+
+    // 将文件系统所有被修改的缓存块写回块设备
+    pub fn sync(&self) {
+        sync_device(&self.block_dev);
     }
 
     // 根inode节点，inode编号为0
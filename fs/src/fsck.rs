@@ -0,0 +1,279 @@
+use super::fs::FileSystem;
+use super::inode::{DiskINode, FLAG_HASHED_DIR};
+use super::block_cache::{get_block_cache, BLOCK_SIZE};
+use super::dir::{parse_entries, name_bucket, DirRecord, DIR_SIZE, BUCKET_SLOTS};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use spin::Mutex;
+
+// 文件系统检查发现的问题
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    // 根目录inode未分配或不是目录，无法检查
+    BadRoot,
+    // data bitmap中已分配，但没有被任何文件引用的块
+    LeakedBlock(u32),
+    // 被文件引用，但data bitmap中未分配的块
+    UnallocatedBlock(u32),
+    // 已经被其他文件引用的块，再次被inode引用
+    DoubleReference { block: u32, inode: u32 },
+    // inode bitmap中已分配，但不在目录树中的inode
+    LeakedInode(u32),
+    // 指向无效或未分配inode的目录项
+    DanglingEntry { dir: u32, name: String, inode: u32 },
+    // 无法解析的目录项槽
+    BadEntry { dir: u32, slot: u32 },
+    // 散列目录的大小不是整数个块，或目录项不在文件名所在的桶中
+    BadDirIndex(u32),
+    // inode记录的链接数与指向它的目录项数量不一致
+    BadLinkCount { inode: u32, nlink: u32, entries: u32 },
+    // 文件大小超出了有效块指针能表示的范围，或目录大小不是目录项的整数倍
+    BadSize { inode: u32, size: u32, valid_size: u32 },
+    // 超级块中的空闲计数与bitmap不一致，(超级块记录的数量, bitmap中的实际数量)
+    BadFreeCount { recorded: (u32, u32), actual: (u32, u32) },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::BadRoot => write!(f, "root inode is not an allocated directory"),
+            Problem::LeakedBlock(block) => write!(f, "block {} is allocated but not referenced", block),
+            Problem::UnallocatedBlock(block) => write!(f, "block {} is referenced but not allocated", block),
+            Problem::DoubleReference { block, inode } => write!(f, "block {} referenced by inode {} is already in use", block, inode),
+            Problem::LeakedInode(inode) => write!(f, "inode {} is allocated but not reachable", inode),
+            Problem::DanglingEntry { dir, name, inode } => write!(f, "entry {} in directory inode {} points to invalid inode {}", name, dir, inode),
+            Problem::BadEntry { dir, slot } => write!(f, "entry slot {} in directory inode {} is corrupted", slot, dir),
+            Problem::BadDirIndex(dir) => write!(f, "hash index of directory inode {} is inconsistent", dir),
+            Problem::BadLinkCount { inode, nlink, entries } => write!(f, "inode {} has link count {}, but {} entries point to it", inode, nlink, entries),
+            Problem::BadSize { inode, size, valid_size } => write!(f, "inode {} has size {}, only {} bytes are valid", inode, size, valid_size),
+            Problem::BadFreeCount { recorded, actual } => write!(f, "super block records {} free inodes and {} free blocks, bitmaps have {} and {}", recorded.0, recorded.1, actual.0, actual.1),
+        }
+    }
+}
+
+// 检查文件系统：从根目录遍历目录树，比较inode和data bitmap与实际引用的inode和块
+// fix为true时修复发现的问题：截断大小错误的文件，删除无效的目录项，散列不一致的目录退化为线性目录，
+// 按目录项数量修正链接数，按实际引用重建bitmap和空闲计数
+// 返回发现的所有问题
+pub fn fsck(fs: &Arc<Mutex<FileSystem>>, fix: bool) -> Vec<Problem> {
+    let fs = fs.lock();
+    let block_dev = Arc::clone(&fs.block_dev);
+    let mut problems: Vec<Problem> = Vec::new();
+    let inode_count = fs.inode_bitmap.bits();
+    let is_valid = |block_id: u32| {fs.is_data_block(block_id)};
+    // 根目录必须是已分配的目录
+    let (root_block, _, root_offset) = fs.get_inode_block_id(0);
+    let root_is_dir = get_block_cache(root_block as usize, Arc::clone(&block_dev))
+    .lock()
+    .read(root_offset as usize, |disk_inode: &DiskINode| {disk_inode.is_dir()});
+    if !fs.inode_bitmap.is_allocated(0, Arc::clone(&block_dev)) || !root_is_dir {
+        problems.push(Problem::BadRoot);
+        return problems;
+    }
+    // 超级块中的空闲计数应与bitmap一致
+    let statfs = fs.statfs();
+    let recorded = (statfs.free_inodes, statfs.free_blocks);
+    let actual = fs.count_free();
+    if recorded != actual {
+        problems.push(Problem::BadFreeCount { recorded, actual });
+    }
+    // 修复分多个事务提交，每个事务不超过日志容量，中途崩溃后重新运行fsck即可继续修复
+    let checkpoint = || {
+        if fix {
+            fs.checkpoint();
+        }
+    };
+    if fix {
+        fs.begin();
+    }
+    // 目录树中可以到达的inode
+    let mut reachable = vec![false; inode_count as usize];
+    // 指向每个inode的目录项数量，不包括"."和".."
+    let mut entries = vec![0u32; inode_count as usize];
+    // 被引用的块 -> 引用它的inode
+    let mut referenced: BTreeMap<u32, u32> = BTreeMap::new();
+    let mut stack: Vec<u32> = vec![0];
+    reachable[0] = true;
+    while let Some(inode_id) = stack.pop() {
+        checkpoint();
+        let (block_id, _, offset) = fs.get_inode_block_id(inode_id);
+        let inode_cache = get_block_cache(block_id as usize, Arc::clone(&block_dev));
+        let (size, is_dir, is_hashed, blocks, bad_seq) = inode_cache.lock().read(offset as usize, |disk_inode: &DiskINode| {
+            let (blocks, bad_seq) = disk_inode.referenced_blocks(is_valid, Arc::clone(&block_dev));
+            (disk_inode.size, disk_inode.is_dir(), disk_inode.is_hashed_dir(), blocks, bad_seq)
+        });
+        // 记录引用的块，遇到已被引用的块时，从该块开始的部分视为无效
+        let mut valid_blocks = bad_seq.unwrap_or(DiskINode::data_blocks_for_size(size));
+        let mut inserted: Vec<(u32, u32)> = Vec::new();
+        for (block, seq) in blocks {
+            if seq >= valid_blocks {
+                break;
+            }
+            if referenced.contains_key(&block) {
+                problems.push(Problem::DoubleReference { block, inode: inode_id });
+                valid_blocks = seq;
+                break;
+            }
+            referenced.insert(block, inode_id);
+            inserted.push((block, seq));
+        }
+        // 截断位置对应的索引块同样不再被引用
+        for (block, seq) in inserted {
+            if seq >= valid_blocks {
+                referenced.remove(&block);
+            }
+        }
+        let mut valid_size = size.min(valid_blocks * BLOCK_SIZE as u32);
+        if is_dir {
+            valid_size -= valid_size % DIR_SIZE;
+        }
+        if valid_size != size {
+            problems.push(Problem::BadSize { inode: inode_id, size, valid_size });
+            if fix {
+                inode_cache.lock().modify(offset as usize, |disk_inode: &mut DiskINode| {
+                    disk_inode.forget_blocks_from(valid_blocks, is_valid, Arc::clone(&block_dev));
+                    disk_inode.size = valid_size;
+                });
+            }
+        }
+        if !is_dir {
+            continue;
+        }
+        // 检查目录项，将子目录和文件加入遍历
+        let records = inode_cache.lock().read(offset as usize, |disk_inode: &DiskINode| {
+            let mut data = vec![0u8; valid_size as usize];
+            disk_inode.read(0, &mut data, Arc::clone(&block_dev));
+            parse_entries(&data)
+        });
+        // 散列目录中的目录项必须都在文件名所在的桶中，否则查找不到，退化为线性目录后仍能找到
+        if is_hashed && !is_hashed_layout(valid_size, &records) {
+            problems.push(Problem::BadDirIndex(inode_id));
+            if fix {
+                inode_cache.lock().modify(offset as usize, |disk_inode: &mut DiskINode| {
+                    disk_inode.flags &= !FLAG_HASHED_DIR;
+                });
+            }
+        }
+        for record in records {
+            // 置空目录项占用的所有槽
+            let clear_record = || {
+                let empty = vec![0u8; (record.slots * DIR_SIZE) as usize];
+                inode_cache.lock().modify(offset as usize, |disk_inode: &mut DiskINode| {
+                    disk_inode.write(record.slot * DIR_SIZE, &empty, Arc::clone(&block_dev));
+                });
+            };
+            let name = match record.name {
+                Some(ref name) => name,
+                None => {
+                    problems.push(Problem::BadEntry { dir: inode_id, slot: record.slot });
+                    if fix {
+                        clear_record();
+                        checkpoint();
+                    }
+                    continue;
+                }
+            };
+            if name == "." || name == ".." {
+                continue;
+            }
+            let child = record.inode_id;
+            // 目录项指向的inode必须存在且已分配，目录只能出现在一个目录项中，文件可以有多个硬链接
+            let valid = child < inode_count && fs.inode_bitmap.is_allocated(child, Arc::clone(&block_dev));
+            if !valid || (reachable[child as usize] && is_directory(&fs, child)) {
+                problems.push(Problem::DanglingEntry { dir: inode_id, name: name.clone(), inode: child });
+                if fix {
+                    clear_record();
+                    checkpoint();
+                }
+                continue;
+            }
+            entries[child as usize] += 1;
+            if !reachable[child as usize] {
+                reachable[child as usize] = true;
+                stack.push(child);
+            }
+        }
+    }
+    // 比较链接数，根目录没有指向它的目录项
+    for inode_id in 1..inode_count {
+        if !reachable[inode_id as usize] {
+            continue;
+        }
+        let (block_id, _, offset) = fs.get_inode_block_id(inode_id);
+        let inode_cache = get_block_cache(block_id as usize, Arc::clone(&block_dev));
+        let nlink = inode_cache.lock().read(offset as usize, |disk_inode: &DiskINode| {disk_inode.link_count()});
+        let count = entries[inode_id as usize];
+        if nlink != count {
+            problems.push(Problem::BadLinkCount { inode: inode_id, nlink, entries: count });
+            if fix {
+                inode_cache.lock().modify(offset as usize, |disk_inode: &mut DiskINode| {
+                    disk_inode.nlink = count;
+                });
+                checkpoint();
+            }
+        }
+    }
+    // 比较inode bitmap
+    for inode_id in 0..inode_count {
+        if fs.inode_bitmap.is_allocated(inode_id, Arc::clone(&block_dev)) && !reachable[inode_id as usize] {
+            problems.push(Problem::LeakedInode(inode_id));
+            if fix {
+                fs.inode_bitmap.dealloc(inode_id, Arc::clone(&block_dev));
+                checkpoint();
+            }
+        }
+    }
+    // 比较data bitmap
+    for seq in 0..fs.data_bitmap.bits() {
+        let block_id = fs.get_data_block_id(seq);
+        let allocated = fs.data_bitmap.is_allocated(seq, Arc::clone(&block_dev));
+        let used = referenced.contains_key(&block_id);
+        if allocated && !used {
+            problems.push(Problem::LeakedBlock(block_id));
+            if fix {
+                fs.data_bitmap.dealloc(seq, Arc::clone(&block_dev));
+                checkpoint();
+            }
+        }else if !allocated && used {
+            problems.push(Problem::UnallocatedBlock(block_id));
+            if fix {
+                fs.data_bitmap.set_allocated(seq, Arc::clone(&block_dev));
+                checkpoint();
+            }
+        }
+    }
+    if fix {
+        // bitmap修复后重新统计空闲计数
+        fs.recount_free();
+        fs.commit();
+        fs.sync();
+    }
+    return problems;
+}
+
+// inode是否为目录
+fn is_directory(fs: &FileSystem, inode_id: u32) -> bool {
+    let (block_id, _, offset) = fs.get_inode_block_id(inode_id);
+    return get_block_cache(block_id as usize, Arc::clone(&fs.block_dev))
+    .lock()
+    .read(offset as usize, |disk_inode: &DiskINode| {disk_inode.is_dir()});
+}
+
+// 目录大小和目录项是否符合散列目录的布局
+fn is_hashed_layout(size: u32, records: &[DirRecord]) -> bool {
+    let buckets = size / BLOCK_SIZE as u32;
+    if !size.is_multiple_of(BLOCK_SIZE as u32) {
+        return false;
+    }
+    return records.iter().all(|record| {
+        let in_block = record.slot % BUCKET_SLOTS + record.slots <= BUCKET_SLOTS;
+        match record.name {
+            Some(ref name) => in_block && record.slot / BUCKET_SLOTS == name_bucket(name, buckets),
+            None => true,
+        }
+    });
+}
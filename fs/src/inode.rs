@@ -1,12 +1,14 @@
 use super::block_cache::{BLOCK_SIZE, get_block_cache, BlockCache};
 use super::block_device::BlockDevice;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use spin::Mutex;
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum INodeType {
     File,
     Directory,
+    Symlink, // 符号链接，数据为目标路径
 }
 // 一个inode的大小
 pub const INODE_SIZE: u32 = 128;
@@ -24,41 +26,95 @@ const INDIRECT1_SIZE_LIMIT: u32 = DIRECT_SIZE_LIMIT + BLOCK_SIZE as u32 * INDIRE
 
 const INDEX_PER_BLOCK: u32 = INDIRECT1_BLOCK_LIMIT;
 
+// 新建文件的默认权限 rw-r--r--
+pub const DEFAULT_FILE_MODE: u32 = 0o644;
+// 新建目录的默认权限 rwxr-xr-x
+pub const DEFAULT_DIR_MODE: u32 = 0o755;
+// 符号链接的权限 rwxrwxrwx，访问权限由目标决定
+pub const DEFAULT_SYMLINK_MODE: u32 = 0o777;
+// 权限位掩码
+pub const MODE_MASK: u32 = 0o777;
+// inode标志：散列目录，目录项按文件名的散列值存放在对应的数据块中
+pub const FLAG_HASHED_DIR: u32 = 1 << 0;
+
 // inode，大小对齐128字节
-#[repr(align(128))]
+// 使用C布局，新增的字段只能追加在末尾，旧镜像中这些字段为0
+#[repr(C, align(128))]
 pub struct DiskINode {
     pub size: u32,                           // 单个文件大小不超过4GiB
     pub indexes: [u32; DIRECT_INDEX_BLOCKS as usize], // 12个直接指针，直接指向数据块，最多48KiB
     pub indirect1: u32,     // 一级间接索引，指向一个全索引块，全索引块的4KiB全部记录数据块指针，共1024个指针，索引1024*4KiB = 4MiB数据
     pub indirect2: u32,     // 二级间接索引，指向一个二级全索引块，共1024个指针指向一级索引，所以共1024 * 1024 * 4KiB = 4GiB数据
     pub _type: INodeType,
+    pub mode: u32,          // 权限位 rwxrwxrwx
+    pub uid: u32,           // 所有者用户id
+    pub gid: u32,           // 所有者组id
+    pub atime: u32,         // 最后访问时间，单位秒，由文件系统的时钟提供
+    pub mtime: u32,         // 文件内容最后修改时间
+    pub ctime: u32,         // inode最后修改时间
+    pub flags: u32,         // inode标志，旧镜像为0
+    pub nlink: u32,         // 指向该inode的目录项数量，不包括"."和".."，旧镜像为0
 }
 
 impl DiskINode {
-    pub fn init(&mut self, _type: INodeType) {
+    // 初始化inode，now为创建时间，权限为类型对应的默认权限，所有者为0
+    pub fn init(&mut self, _type: INodeType, now: u32) {
         self._type = _type;
         self.indexes = [0u32; DIRECT_INDEX_BLOCKS as usize];
         self.size = 0;
         self.indirect1 = 0;
         self.indirect2 = 0;
+        self.mode = match _type {
+            INodeType::File => DEFAULT_FILE_MODE,
+            INodeType::Directory => DEFAULT_DIR_MODE,
+            INodeType::Symlink => DEFAULT_SYMLINK_MODE,
+        };
+        self.uid = 0;
+        self.gid = 0;
+        self.atime = now;
+        self.mtime = now;
+        self.ctime = now;
+        self.flags = 0;
+        self.nlink = 1;
+    }
+
+    // 链接数，旧镜像中的inode没有记录链接数，只有一个目录项
+    pub fn link_count(&self) -> u32 {
+        return self.nlink.max(1);
+    }
+
+    // 文件内容被修改，更新mtime和ctime
+    pub fn touch(&mut self, now: u32) {
+        self.mtime = now;
+        self.ctime = now;
     }
 
     pub fn is_dir(&self) -> bool {
         return self._type == INodeType::Directory;
     }
 
+    pub fn is_symlink(&self) -> bool {
+        return self._type == INodeType::Symlink;
+    }
+
+    // 是否为散列目录，旧镜像中的目录和退化后的目录使用线性查找
+    pub fn is_hashed_dir(&self) -> bool {
+        return self.is_dir() && self.flags & FLAG_HASHED_DIR != 0;
+    }
+
     // 文件占用的数据块总数，由文件大小对数据块大小向上取整获得
     pub fn data_blocks(&self) -> u32 {
         return Self::data_blocks_for_size(self.size);
     }
 
     pub fn data_blocks_for_size(size: u32) -> u32 {
-        // 向上取整
-        return (size + BLOCK_SIZE as u32 - 1) / BLOCK_SIZE as u32;
+        // 向上取整，div_ceil不会在接近4GiB的大小上溢出
+        return size.div_ceil(BLOCK_SIZE as u32);
     }
 
+    // 没有空洞的文件需要的一级和二级索引块数量
     pub fn index_blocks_for_size(size: u32) -> u32 {
-        let mut data_blocks = (size + BLOCK_SIZE as u32 - 1) / BLOCK_SIZE as u32;
+        let mut data_blocks = Self::data_blocks_for_size(size);
         let mut total = 0;
         // 大小在直接索引范围内
         if data_blocks <= DIRECT_INDEX_BLOCKS {
@@ -75,7 +131,7 @@ impl DiskINode {
             // 一个二级索引块和若干个一级索引块
             total += data_blocks / INDIRECT1_BLOCK_LIMIT + 1;
             // 有余数，需要额外分配一个一级索引块
-            if data_blocks % INDIRECT1_BLOCK_LIMIT == 0 {
+            if !data_blocks.is_multiple_of(INDIRECT1_BLOCK_LIMIT) {
                 total += 1;
             }
             return total;
@@ -87,37 +143,65 @@ impl DiskINode {
         return self.data_blocks() + Self::index_blocks_for_size(self.size) + 1;
     }
 
-    // 根据块顺序获取第seq个数据块的磁盘块id
+    // 根据块顺序获取第seq个数据块的磁盘块id，空洞返回0
     pub fn get_block_id(&self, seq: u32, block_dev: Arc<dyn BlockDevice>) -> u32 {
         assert!(self.data_blocks() > seq);
-        let mut blocks = seq + 1;
-        if blocks <= DIRECT_INDEX_BLOCKS {
-            return self.indexes[blocks as usize - 1];
-        }
-        // 减去直接索引的节点数量
-        blocks -= DIRECT_INDEX_BLOCKS;
-        if blocks <= INDIRECT1_BLOCK_LIMIT {
-            return get_block_cache(self.indirect1 as usize, Arc::clone(&block_dev))
-            .lock()
-            .read(0, |indexes: &[u32; INDEX_PER_BLOCK as usize]| {
-                return indexes[blocks as usize - 1];
-            });
-        }else {
-            // 减去一级的block数量
-            blocks -= INDIRECT1_BLOCK_LIMIT;
-            // 从二级索引获取一级索引块id
-            let id = get_block_cache(self.indirect2 as usize, Arc::clone(&block_dev))
-            .lock()
-            .read(0, |l2_idxs: &[u32; 1024]| {
-                return l2_idxs[((blocks - 1) / INDEX_PER_BLOCK) as usize];
-            });
-            // 从一级索引块获取data块id
-            return get_block_cache(id as usize, Arc::clone(&block_dev))
-            .lock()
-            .read(((blocks - 1) % INDEX_PER_BLOCK) as usize, |id: &u32| {
-                return *id;
-            });
+        if seq < DIRECT_INDEX_BLOCKS {
+            return self.indexes[seq as usize];
+        }
+        // 减去直接索引的块数量
+        let seq = seq - DIRECT_INDEX_BLOCKS;
+        if seq < INDIRECT1_BLOCK_LIMIT {
+            return read_index(self.indirect1, seq, block_dev);
+        }
+        // 减去一级索引的块数量，从二级索引获取一级索引块id，再从一级索引块获取data块id
+        let seq = seq - INDIRECT1_BLOCK_LIMIT;
+        let l1_id = read_index(self.indirect2, seq / INDEX_PER_BLOCK, Arc::clone(&block_dev));
+        return read_index(l1_id, seq % INDEX_PER_BLOCK, block_dev);
+    }
+
+    // 将第seq个数据块的指针设置为block_id，缺少的一级和二级索引块从index_blocks中取出
+    // 需要的索引块数量由missing_index_blocks计算
+    pub fn set_block_id(&mut self, seq: u32, block_id: u32, index_blocks: &mut Vec<u32>, block_dev: Arc<dyn BlockDevice>) {
+        if seq < DIRECT_INDEX_BLOCKS {
+            self.indexes[seq as usize] = block_id;
+            return;
+        }
+        let seq = seq - DIRECT_INDEX_BLOCKS;
+        if seq < INDIRECT1_BLOCK_LIMIT {
+            if self.indirect1 == 0 {
+                self.indirect1 = index_blocks.pop().unwrap();
+            }
+            write_index(self.indirect1, seq, block_id, block_dev);
+            return;
+        }
+        let seq = seq - INDIRECT1_BLOCK_LIMIT;
+        if self.indirect2 == 0 {
+            self.indirect2 = index_blocks.pop().unwrap();
+        }
+        // 二级索引中没有该一级索引块，使用新的索引块
+        let mut l1_id = read_index(self.indirect2, seq / INDEX_PER_BLOCK, Arc::clone(&block_dev));
+        if l1_id == 0 {
+            l1_id = index_blocks.pop().unwrap();
+            write_index(self.indirect2, seq / INDEX_PER_BLOCK, l1_id, Arc::clone(&block_dev));
+        }
+        write_index(l1_id, seq % INDEX_PER_BLOCK, block_id, block_dev);
+    }
+
+    // 设置第seq个数据块的指针时需要新分配的索引块数量
+    pub fn missing_index_blocks(&self, seq: u32, block_dev: Arc<dyn BlockDevice>) -> u32 {
+        if seq < DIRECT_INDEX_BLOCKS {
+            return 0;
+        }
+        let seq = seq - DIRECT_INDEX_BLOCKS;
+        if seq < INDIRECT1_BLOCK_LIMIT {
+            return if self.indirect1 == 0 {1} else {0};
+        }
+        let seq = seq - INDIRECT1_BLOCK_LIMIT;
+        if self.indirect2 == 0 {
+            return 2;
         }
+        return if read_index(self.indirect2, seq / INDEX_PER_BLOCK, block_dev) == 0 {1} else {0};
     }
 
     // 获取文件中偏移位置offset所对应的磁盘块编号
@@ -133,49 +217,42 @@ impl DiskINode {
         return get_block_cache(block_id as usize, Arc::clone(&block_dev));
     }
 
-    // 从offset读取文件数据到buf中
-    pub fn read(&self, offset: u32, buf: &mut [u8], block_dev: Arc<dyn BlockDevice>) {
-        let len = buf.len() as u32;
-        assert!(self.size > offset && self.size >= offset + len);
-        // 读取结束位置的偏移量
-        let end_off = offset + len - 1;
-        // 结束位置所在的块序号
-        let end_block_seq = end_off / BLOCK_SIZE as u32;
-        // 初始的块内偏移
-        let mut inner_start = offset % BLOCK_SIZE as u32;
-        // 初始的块内结束位置，除了最后一个块，其他都是块末尾
-        let mut inner_end = BLOCK_SIZE;
-        // 顺序读取的最后一个块序号
-        let mut current_block_seq = offset / BLOCK_SIZE as u32;
+    // 从offset读取文件数据到buf中，返回读取的字节数
+    // 读取范围超出文件末尾时只读取到文件末尾，offset位于文件末尾或之后时返回0
+    pub fn read(&self, offset: u32, buf: &mut [u8], block_dev: Arc<dyn BlockDevice>) -> usize {
+        if offset >= self.size {
+            return 0;
+        }
+        // 读取结束位置（不包含）
+        let end = (offset as usize + buf.len()).min(self.size as usize);
+        // 当前读取位置
+        let mut current = offset as usize;
         // buf数组写入位置
         let mut idx = 0;
-        loop {
+        while current < end {
+            // 块内起始偏移，以及本块需要读取的长度，除了最后一个块，其他都读到块末尾
+            let inner_start = current % BLOCK_SIZE;
+            let len = (BLOCK_SIZE - inner_start).min(end - current);
             // 通过inode索引获取块id
-            // 读取文件的io瓶颈，尽量顺序读来减少索引块的IO
-            let block_id = self.get_block_id(current_block_seq, Arc::clone(&block_dev));
-            // 读取块缓存，将缓存内容拷贝
-            get_block_cache(block_id as usize, Arc::clone(&block_dev))
-            .lock()
-            .read(0, |bytes: &[u8; BLOCK_SIZE]| {
-                // 最后一个块的右边界通过endoff计算
-                if current_block_seq == end_block_seq {
-                    inner_end = end_off as usize % BLOCK_SIZE;
-                }
-                buf[idx..].copy_from_slice(&bytes[inner_start as usize..=inner_end]);
-            });
-            // 最后一个block
-            if current_block_seq == end_block_seq {
-                break;
+            let block_id = self.get_block_id((current / BLOCK_SIZE) as u32, Arc::clone(&block_dev));
+            if block_id == 0 {
+                // 空洞读出全0
+                buf[idx..idx + len].fill(0);
             }else {
-                idx += inner_end - inner_start as usize + 1;
-                // 下一个block
-                current_block_seq += 1;
-                // 除了第一个块，其他都是块起始位置
-                inner_start = 0;
+                // 读取块缓存，将缓存内容拷贝
+                get_block_cache(block_id as usize, Arc::clone(&block_dev))
+                .lock()
+                .read(0, |bytes: &[u8; BLOCK_SIZE]| {
+                    buf[idx..idx + len].copy_from_slice(&bytes[inner_start..inner_start + len]);
+                });
             }
+            idx += len;
+            current += len;
         }
+        return idx;
     }
-    // 向inode对应的文件写入数据
+
+    // 向inode对应的文件写入数据，写入范围内的空洞需要先由调用者分配数据块
     pub fn write(&mut self, offset: u32, buf: &[u8], block_dev: Arc<dyn BlockDevice>) {
         let len = buf.len() as u32;
         assert!(len + offset <= self.size);
@@ -183,11 +260,13 @@ impl DiskINode {
         let end_block_seq = end_offset / BLOCK_SIZE as u32;
         let mut current_block_seq = offset / BLOCK_SIZE as u32;
         let mut inner_start = offset as usize % BLOCK_SIZE;
-        let mut inner_end = BLOCK_SIZE;
+        // 块内结束位置（包含），除了最后一个块，其他都是块末尾
+        let mut inner_end = BLOCK_SIZE - 1;
         let mut idx: usize = 0;
         loop {
             // 获取该序号数据块的全局id
             let data_block_id = self.get_block_id(current_block_seq, Arc::clone(&block_dev));
+            assert!(data_block_id != 0, "write to a hole");
             // 修改数据块，写入buf中的数据
             get_block_cache(data_block_id as usize, Arc::clone(&block_dev))
             .lock()
@@ -201,60 +280,261 @@ impl DiskINode {
             if current_block_seq == end_block_seq {
                 break;
             }
+            idx += inner_end - inner_start + 1;
             current_block_seq += 1;
             inner_start = 0;
-            idx += inner_end - inner_start + 1;
         }
     }
 
-    // 向文件添加数据块来增大文件大小
-    // index_blocks为预先计算出来需要的一级和二级索引块
+    // 向文件添加数据块来增大文件大小，new_blocks依次放在原有的最后一个数据块之后
+    // index_blocks为预先计算出来需要的一级和二级索引块，数量为新旧大小的index_blocks_for_size之差
+    // 索引块按需取用，只适用于没有空洞的文件
     pub fn increse_size(&mut self, new_size: u32, new_blocks: Vec<u32>, mut index_blocks: Vec<u32>, block_dev: Arc<dyn BlockDevice>) {
+        // 新数据块的起始序号必须在修改文件大小之前计算
+        let first_seq = self.data_blocks();
+        assert_eq!(first_seq + new_blocks.len() as u32, Self::data_blocks_for_size(new_size), "data blocks do not match new size");
         self.size = new_size;
-        let mut current_blocks = self.total_blocks() + 1;
+        for (i, block_id) in new_blocks.into_iter().enumerate() {
+            self.set_block_id(first_seq + i as u32, block_id, &mut index_blocks, Arc::clone(&block_dev));
+        }
+    }
 
-        for new_block in new_blocks {
-            // 可以直接索引
-            if current_blocks <= DIRECT_INDEX_BLOCKS {
-                self.indexes[current_blocks as usize - 1] = new_block;
-            }else if current_blocks <= INDIRECT1_BLOCK_LIMIT + DIRECT_INDEX_BLOCKS { // 一级索引
-                let indirect1_seq = current_blocks - DIRECT_INDEX_BLOCKS - 1;
-                // 一级索引不存在，通过参数的idx_blocks传入
-                if self.indirect1 == 0 {
-                    self.indirect1 = index_blocks.pop().unwrap();
-                }
-                // 在一级索引块中修改第n个block_id
-                get_block_cache(self.indirect1 as usize, Arc::clone(&block_dev))
+    // 缩小文件到new_size，返回不再使用的数据块和已经清空的索引块，由调用者回收
+    // 空洞没有分配块，为0的块指针会被跳过
+    pub fn decrease_size(&mut self, new_size: u32, block_dev: Arc<dyn BlockDevice>) -> Vec<u32> {
+        assert!(new_size <= self.size, "new size larger than current size");
+        let mut freed: Vec<u32> = Vec::new();
+        let old_blocks = self.data_blocks();
+        let new_blocks = Self::data_blocks_for_size(new_size);
+        // 最后一个保留块中新大小之后的数据清零，避免文件再次增大时读到旧数据
+        if !new_size.is_multiple_of(BLOCK_SIZE as u32) {
+            let block_id = self.get_block_id(new_blocks - 1, Arc::clone(&block_dev));
+            if block_id != 0 {
+                get_block_cache(block_id as usize, Arc::clone(&block_dev))
                 .lock()
-                .modify(indirect1_seq as usize * 4, |block_id: &mut u32| {
-                    *block_id = new_block;
-                })
-            }else {
-                // 二级索引不存在，通过参数创建
-                if self.indirect2 == 0 {
-                    self.indirect2 = index_blocks.pop().unwrap();
-                }
-                // 在二级索引中的序号
-                let indirect2_seq = current_blocks - INDIRECT1_BLOCK_LIMIT - DIRECT_INDEX_BLOCKS - 1;
-                get_block_cache(self.indirect2 as usize, Arc::clone(&block_dev))
+                .modify(0, |data: &mut [u8; BLOCK_SIZE]| {
+                    data[new_size as usize % BLOCK_SIZE..].fill(0);
+                });
+            }
+        }
+        self.size = new_size;
+        // 直接索引范围
+        for seq in new_blocks.min(DIRECT_INDEX_BLOCKS)..old_blocks.min(DIRECT_INDEX_BLOCKS) {
+            if self.indexes[seq as usize] != 0 {
+                freed.push(self.indexes[seq as usize]);
+            }
+            self.indexes[seq as usize] = 0;
+        }
+        if old_blocks <= DIRECT_INDEX_BLOCKS {
+            return freed;
+        }
+        // 一级索引范围，块序号相对于一级索引块
+        let start = new_blocks.max(DIRECT_INDEX_BLOCKS) - DIRECT_INDEX_BLOCKS;
+        let end = old_blocks.min(DIRECT_INDEX_BLOCKS + INDIRECT1_BLOCK_LIMIT) - DIRECT_INDEX_BLOCKS;
+        if self.indirect1 != 0 {
+            if start < end {
+                clear_indexes(self.indirect1, start, end, &mut freed, Arc::clone(&block_dev));
+            }
+            // 一级索引块中不再有数据块
+            if start == 0 {
+                freed.push(self.indirect1);
+                self.indirect1 = 0;
+            }
+        }
+        if old_blocks <= DIRECT_INDEX_BLOCKS + INDIRECT1_BLOCK_LIMIT || self.indirect2 == 0 {
+            return freed;
+        }
+        // 二级索引范围，块序号相对于二级索引块
+        let start = new_blocks.max(DIRECT_INDEX_BLOCKS + INDIRECT1_BLOCK_LIMIT) - DIRECT_INDEX_BLOCKS - INDIRECT1_BLOCK_LIMIT;
+        let end = old_blocks - DIRECT_INDEX_BLOCKS - INDIRECT1_BLOCK_LIMIT;
+        let l1_ids: Vec<u32> = get_block_cache(self.indirect2 as usize, Arc::clone(&block_dev))
+        .lock()
+        .read(0, |l2_idxs: &[u32; INDEX_PER_BLOCK as usize]| {
+            l2_idxs.to_vec()
+        });
+        for l1_seq in start / INDEX_PER_BLOCK..end.div_ceil(INDEX_PER_BLOCK) {
+            let l1_id = l1_ids[l1_seq as usize];
+            // 整个一级索引块范围都是空洞
+            if l1_id == 0 {
+                continue;
+            }
+            // 该一级索引块中需要回收的数据块范围
+            let l1_base = l1_seq * INDEX_PER_BLOCK;
+            let inner_start = start.max(l1_base) - l1_base;
+            let inner_end = end.min(l1_base + INDEX_PER_BLOCK) - l1_base;
+            clear_indexes(l1_id, inner_start, inner_end, &mut freed, Arc::clone(&block_dev));
+            // 一级索引块已经清空，从二级索引中移除
+            if inner_start == 0 {
+                freed.push(l1_id);
+                write_index(self.indirect2, l1_seq, 0, Arc::clone(&block_dev));
+            }
+        }
+        if start == 0 {
+            freed.push(self.indirect2);
+            self.indirect2 = 0;
+        }
+        return freed;
+    }
+
+    // 列出文件引用的索引块和数据块，每项为 (块号, 该块对应的第一个数据块序号)，按数据块序号排列
+    // 为0的块指针是空洞，不引用任何块；遇到is_valid判断无效的块号时停止，同时返回该块对应的数据块序号
+    pub fn referenced_blocks(&self, is_valid: impl Fn(u32) -> bool, block_dev: Arc<dyn BlockDevice>) -> (Vec<(u32, u32)>, Option<u32>) {
+        let mut blocks: Vec<(u32, u32)> = Vec::new();
+        let data_blocks = self.data_blocks();
+        // 直接索引范围
+        for seq in 0..data_blocks.min(DIRECT_INDEX_BLOCKS) {
+            let block_id = self.indexes[seq as usize];
+            if block_id == 0 {
+                continue;
+            }
+            if !is_valid(block_id) {
+                return (blocks, Some(seq));
+            }
+            blocks.push((block_id, seq));
+        }
+        if data_blocks <= DIRECT_INDEX_BLOCKS {
+            return (blocks, None);
+        }
+        // 一级索引范围
+        if self.indirect1 != 0 {
+            let count = (data_blocks - DIRECT_INDEX_BLOCKS).min(INDIRECT1_BLOCK_LIMIT);
+            if let Some(bad_seq) = referenced_in_index(self.indirect1, DIRECT_INDEX_BLOCKS, count, &is_valid, &mut blocks, Arc::clone(&block_dev)) {
+                return (blocks, Some(bad_seq));
+            }
+        }
+        if data_blocks <= DIRECT_INDEX_BLOCKS + INDIRECT1_BLOCK_LIMIT || self.indirect2 == 0 {
+            return (blocks, None);
+        }
+        // 二级索引范围
+        let base = DIRECT_INDEX_BLOCKS + INDIRECT1_BLOCK_LIMIT;
+        if !is_valid(self.indirect2) {
+            return (blocks, Some(base));
+        }
+        blocks.push((self.indirect2, base));
+        let l2_ids = get_block_cache(self.indirect2 as usize, Arc::clone(&block_dev))
+        .lock()
+        .read(0, |indexes: &[u32; INDEX_PER_BLOCK as usize]| {
+            indexes.to_vec()
+        });
+        let rest = data_blocks - base;
+        for l1_seq in 0..rest.div_ceil(INDEX_PER_BLOCK) {
+            let l1_base = base + l1_seq * INDEX_PER_BLOCK;
+            let l1_id = l2_ids[l1_seq as usize];
+            if l1_id == 0 {
+                continue;
+            }
+            let count = (data_blocks - l1_base).min(INDEX_PER_BLOCK);
+            if let Some(bad_seq) = referenced_in_index(l1_id, l1_base, count, &is_valid, &mut blocks, Arc::clone(&block_dev)) {
+                return (blocks, Some(bad_seq));
+            }
+        }
+        return (blocks, None);
+    }
+
+    // 丢弃从第seq个数据块开始的所有块指针并缩小文件，不回收这些块
+    // 用于修复损坏的inode，块指针可能无效，所以只访问is_valid判断有效的索引块
+    pub fn forget_blocks_from(&mut self, seq: u32, is_valid: impl Fn(u32) -> bool, block_dev: Arc<dyn BlockDevice>) {
+        self.size = self.size.min(seq * BLOCK_SIZE as u32);
+        for i in seq.min(DIRECT_INDEX_BLOCKS)..DIRECT_INDEX_BLOCKS {
+            self.indexes[i as usize] = 0;
+        }
+        // 一级索引范围
+        if seq <= DIRECT_INDEX_BLOCKS {
+            self.indirect1 = 0;
+        }else if seq < DIRECT_INDEX_BLOCKS + INDIRECT1_BLOCK_LIMIT && is_valid(self.indirect1) {
+            get_block_cache(self.indirect1 as usize, Arc::clone(&block_dev))
+            .lock()
+            .modify(0, |indexes: &mut [u32; INDEX_PER_BLOCK as usize]| {
+                indexes[(seq - DIRECT_INDEX_BLOCKS) as usize..].fill(0);
+            });
+        }
+        // 二级索引范围
+        let base = DIRECT_INDEX_BLOCKS + INDIRECT1_BLOCK_LIMIT;
+        if seq <= base {
+            self.indirect2 = 0;
+        }else if is_valid(self.indirect2) {
+            let l1_seq = (seq - base) / INDEX_PER_BLOCK;
+            let inner = (seq - base) % INDEX_PER_BLOCK;
+            let l1_id = get_block_cache(self.indirect2 as usize, Arc::clone(&block_dev))
+            .lock()
+            .modify(0, |indexes: &mut [u32; INDEX_PER_BLOCK as usize]| {
+                // 部分保留的一级索引块仍留在二级索引中
+                let keep = if inner > 0 {l1_seq + 1} else {l1_seq};
+                indexes[keep as usize..].fill(0);
+                indexes[l1_seq as usize]
+            });
+            if inner > 0 && is_valid(l1_id) {
+                get_block_cache(l1_id as usize, Arc::clone(&block_dev))
                 .lock()
-                .modify(self.indirect2 as usize, |block_ids: &mut [u32;INDIRECT1_BLOCK_LIMIT as usize]| {
-                    let sec_level_seq = indirect2_seq / INDIRECT1_BLOCK_LIMIT;
-                    let first_level_seq = indirect2_seq % INDIRECT1_BLOCK_LIMIT;
-                    // 二级索引中没有该一级索引编号，分配新的块号
-                    if block_ids[sec_level_seq as usize] == 0 {
-                        block_ids[sec_level_seq as usize] = index_blocks.pop().unwrap();
-                    }
-                    // 找到一级索引块，修改对应序号的block id
-                    get_block_cache(block_ids[sec_level_seq as usize] as usize, Arc::clone(&block_dev))
-                    .lock()
-                    .modify(first_level_seq as usize, |block_id: &mut u32| {
-                        *block_id = new_block;
-                    })
-                })
+                .modify(0, |indexes: &mut [u32; INDEX_PER_BLOCK as usize]| {
+                    indexes[inner as usize..].fill(0);
+                });
             }
-            current_blocks += 1;
         }
     }
+
+    // 清空文件，返回文件占用的所有数据块和索引块，由调用者回收
+    pub fn clear_size(&mut self, block_dev: Arc<dyn BlockDevice>) -> Vec<u32> {
+        return self.decrease_size(0, block_dev);
+    }
 }
 
+// 读取索引块中第idx个块指针，索引块为0时整个范围都是空洞，返回0
+fn read_index(index_block: u32, idx: u32, block_dev: Arc<dyn BlockDevice>) -> u32 {
+    if index_block == 0 {
+        return 0;
+    }
+    return get_block_cache(index_block as usize, block_dev)
+    .lock()
+    .read(0, |indexes: &[u32; INDEX_PER_BLOCK as usize]| {
+        indexes[idx as usize]
+    });
+}
+
+// 修改索引块中第idx个块指针
+fn write_index(index_block: u32, idx: u32, block_id: u32, block_dev: Arc<dyn BlockDevice>) {
+    get_block_cache(index_block as usize, block_dev)
+    .lock()
+    .modify(0, |indexes: &mut [u32; INDEX_PER_BLOCK as usize]| {
+        indexes[idx as usize] = block_id;
+    });
+}
+
+// 清空索引块中[start, end)范围的块指针，不为0的块号加入freed
+fn clear_indexes(index_block: u32, start: u32, end: u32, freed: &mut Vec<u32>, block_dev: Arc<dyn BlockDevice>) {
+    get_block_cache(index_block as usize, block_dev)
+    .lock()
+    .modify(0, |indexes: &mut [u32; INDEX_PER_BLOCK as usize]| {
+        for i in start..end {
+            if indexes[i as usize] != 0 {
+                freed.push(indexes[i as usize]);
+            }
+            indexes[i as usize] = 0;
+        }
+    });
+}
+
+// 列出一级索引块及其前count个块指针引用的数据块，base为第一个块指针对应的数据块序号
+// 遇到无效块号时返回该块对应的数据块序号
+fn referenced_in_index(index_block: u32, base: u32, count: u32, is_valid: &impl Fn(u32) -> bool, blocks: &mut Vec<(u32, u32)>, block_dev: Arc<dyn BlockDevice>) -> Option<u32> {
+    if !is_valid(index_block) {
+        return Some(base);
+    }
+    blocks.push((index_block, base));
+    let ids = get_block_cache(index_block as usize, block_dev)
+    .lock()
+    .read(0, |indexes: &[u32; INDEX_PER_BLOCK as usize]| {
+        indexes.to_vec()
+    });
+    for i in 0..count {
+        let block_id = ids[i as usize];
+        if block_id == 0 {
+            continue;
+        }
+        if !is_valid(block_id) {
+            return Some(base + i);
+        }
+        blocks.push((block_id, base + i));
+    }
+    return None;
+}
@@ -0,0 +1,135 @@
+use super::block_cache::{get_block_cache, BlockCache, BLOCK_SIZE};
+use super::block_device::BlockDevice;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+// 新建文件系统时日志区域的块数，包括一个日志头块
+pub const JOURNAL_BLOCKS: u32 = 64;
+const JOURNAL_MAGIC: u32 = 0x4a524e4c;
+// 日志头中最多能记录的块号数量
+const HEADER_LIMIT: usize = BLOCK_SIZE / 4 - 2;
+
+// 日志头，位于日志区域的第一个块，大小为一个块
+// 依次为 magic、事务包含的块数、第i个日志块对应的原块号
+// count不为0表示有一个已提交但还未写回原位置的事务
+// 内核栈较小，日志头放在堆上
+struct JournalHeader(Vec<u32>);
+
+// 写前日志，磁盘块布局：| header | log blocks |
+// 事务提交时先将修改过的块写入日志块，再写入日志头作为提交点，最后写回原位置并清空日志头
+// 打开文件系统时重放已提交的事务，保证一个事务中的修改要么全部生效、要么全部不生效
+pub struct Journal {
+    start: u32,  // 日志区域起始块号
+    blocks: u32, // 日志区域块数
+    block_dev: Arc<dyn BlockDevice>,
+}
+
+impl JournalHeader {
+    fn empty() -> Self {
+        let mut words = vec![0u32; BLOCK_SIZE / 4];
+        words[0] = JOURNAL_MAGIC;
+        return Self(words);
+    }
+
+    fn is_valid(&self) -> bool {
+        return self.0[0] == JOURNAL_MAGIC;
+    }
+
+    fn count(&self) -> usize {
+        return self.0[1] as usize;
+    }
+
+    fn block(&self, i: usize) -> u32 {
+        return self.0[2 + i];
+    }
+
+    // 追加一个日志块，记录它对应的原块号
+    fn push(&mut self, block_id: u32) {
+        let i = self.count();
+        self.0[2 + i] = block_id;
+        self.0[1] += 1;
+    }
+
+    fn to_bytes(&self) -> &[u8] {
+        let ptr = self.0.as_ptr() as *const u8;
+        unsafe {return core::slice::from_raw_parts(ptr, BLOCK_SIZE);}
+    }
+
+    fn to_bytes_mut(&mut self) -> &mut [u8] {
+        let ptr = self.0.as_mut_ptr() as *mut u8;
+        unsafe {return core::slice::from_raw_parts_mut(ptr, BLOCK_SIZE);}
+    }
+}
+
+impl Journal {
+    pub fn new(start: u32, blocks: u32, block_dev: Arc<dyn BlockDevice>) -> Self {
+        return Self {start, blocks, block_dev};
+    }
+
+    // 一个事务最多能记录的块数
+    pub fn capacity(&self) -> usize {
+        return (self.blocks as usize - 1).min(HEADER_LIMIT);
+    }
+
+    // 初始化日志区域，写入空的日志头
+    pub fn init(&self) {
+        self.write_header(&JournalHeader::empty());
+    }
+
+    // 重放已提交但未完成写回的事务
+    // 日志区域的读写都直接访问块设备，不经过块缓存
+    pub fn replay(&self) {
+        let mut header = JournalHeader::empty();
+        self.block_dev.read_block(self.start as usize, header.to_bytes_mut());
+        if !header.is_valid() || header.count() == 0 {
+            return;
+        }
+        let mut buf = vec![0u8; BLOCK_SIZE];
+        for i in 0..header.count().min(self.capacity()) {
+            self.block_dev.read_block(self.start as usize + 1 + i, &mut buf);
+            // 通过块缓存写回，避免缓存中留下旧数据
+            let cache = get_block_cache(header.block(i) as usize, Arc::clone(&self.block_dev));
+            let mut locked = cache.lock();
+            locked.modify(0, |block: &mut [u8; BLOCK_SIZE]| {
+                block.copy_from_slice(&buf);
+            });
+            locked.sync();
+        }
+        self.block_dev.flush();
+        self.write_header(&JournalHeader::empty());
+    }
+
+    // 提交一个事务，参数为事务期间被修改的缓存块
+    pub fn commit(&self, dirty: Vec<Arc<Mutex<BlockCache>>>) {
+        if dirty.is_empty() {
+            return;
+        }
+        // 事务超过日志容量时无法保证原子性，调用者需将大的修改拆分为多个事务
+        // 不能退回到不经过日志直接写回，任何构建中都直接panic
+        assert!(dirty.len() <= self.capacity(), "transaction of {} blocks exceeds journal capacity", dirty.len());
+        // 1. 将修改后的块写入日志块
+        let mut header = JournalHeader::empty();
+        for (i, cache) in dirty.iter().enumerate() {
+            let locked = cache.lock();
+            self.block_dev.write_block(self.start as usize + 1 + i, &locked.cache);
+            header.push(locked.block_id as u32);
+        }
+        self.block_dev.flush();
+        // 2. 写入日志头，日志头落盘后事务即提交
+        self.write_header(&header);
+        // 3. 写回原位置
+        for cache in dirty.iter() {
+            cache.lock().sync();
+        }
+        self.block_dev.flush();
+        // 4. 清空日志头，事务完成
+        self.write_header(&JournalHeader::empty());
+    }
+
+    fn write_header(&self, header: &JournalHeader) {
+        self.block_dev.write_block(self.start as usize, header.to_bytes());
+        self.block_dev.flush();
+    }
+}
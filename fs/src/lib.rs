@@ -1,9 +1,13 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+pub mod error;
 pub mod block_device;
 pub mod block_cache;
 pub mod block_layout;
 pub mod bitmap;
+pub mod journal;
 pub mod inode;
 pub mod dir;
 pub mod fs;
 pub mod vfs;
+pub mod fsck;
 extern crate alloc;
\ No newline at end of file
@@ -1,14 +1,42 @@
 use super::block_device::BlockDevice;
-use super::fs::FileSystem;
-use super::inode::{DiskINode, INodeType};
-use super::block_cache::get_block_cache;
-use super::dir::{DIR_SIZE, DirEntry};
+use super::fs::{FileSystem, StatFs};
+use super::inode::{DiskINode, INodeType, MODE_MASK, FLAG_HASHED_DIR};
+use super::block_cache::{get_block_cache, BLOCK_SIZE};
+use super::dir::{DIR_SIZE, INODE_ID_OFFSET, DirRecord, NAME_LIMIT, BUCKET_SLOTS, MAX_BUCKETS, encode_entry, parse_entries, parse_entries_partial, name_bucket, split_source, splits_name_bucket, find_free_slots, slots_for_name};
+use super::error::{FsError, FsResult};
 use spin::{Mutex, MutexGuard};
 use alloc::sync::Arc;
 use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
 
+// write_at每个事务写入的最大字节数，32个数据块加上相关的bitmap、索引块和inode块不超过日志容量
+const WRITE_CHUNK_SIZE: usize = 32 * BLOCK_SIZE;
+// 缩小文件时每个事务最多回收的数据块数，即一个一级索引块记录的数据块数
+const SHRINK_CHUNK_BLOCKS: u32 = BLOCK_SIZE as u32 / 4;
+// 符号链接目标路径的最大长度
+pub const SYMLINK_TARGET_LIMIT: usize = BLOCK_SIZE;
+// 解析一个路径时最多跟随的符号链接数量
+pub const FOLLOW_LIMIT: u32 = 8;
+
+// 文件状态
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stat {
+    pub inode: u32,         // inode编号
+    pub _type: INodeType,   // 文件类型
+    pub size: u32,          // 文件大小
+    pub blocks: u32,        // 占用的数据块和索引块数量
+    pub mode: u32,          // 权限位
+    pub uid: u32,           // 所有者用户id
+    pub gid: u32,           // 所有者组id
+    pub atime: u32,         // 最后访问时间
+    pub mtime: u32,         // 文件内容最后修改时间
+    pub ctime: u32,         // inode最后修改时间
+    pub nlink: u32,         // 链接数
+}
+
 // 内存记录的INode信息
+#[derive(Clone)]
 pub struct INode {
     pub block_id: u32,              // inode所在的块id
     pub block_offset: u32,          // inode在块内的偏移
@@ -44,122 +72,855 @@ impl INode {
     }
 
     // 在当前目录inode中寻找文件名为name的文件inode
-    pub fn find(&self, name: &str) -> Option<INode> {
-        return self.read_disk_inode(|disk_inode|{
-            self.find_file_inode(name, disk_inode)
-            .map(|id| {
-                // 从文件系统找到inode id对应的inode块
-                let fs = self.fs.lock();
-                let (block_id, _, block_offset) = fs.get_inode_block_id(id);
-                return INode::new(block_id, block_offset, Arc::clone(&self.fs), Arc::clone(&self.block_dev));
-            })
+    pub fn find(&self, name: &str) -> FsResult<INode> {
+        let inode_id = self.read_disk_inode(|disk_inode|{
+            if !disk_inode.is_dir() {
+                return Err(FsError::NotDir);
+            }
+            self.find_file_inode(name, disk_inode)?.ok_or(FsError::NotFound)
+        })?;
+        return Ok(self.inode_from_id(inode_id, &self.fs.lock()));
+    }
+
+    // 从当前目录开始逐级查找路径，如 "bin/sh"，空路径返回当前目录，以'/'开头的路径从根目录开始查找
+    // 路径中的符号链接都会被跟随，跟随超过FOLLOW_LIMIT次时返回TooManyLinks
+    pub fn find_path(&self, path: &str) -> FsResult<INode> {
+        let mut follows = 0;
+        return self.resolve_path(path, &mut follows);
+    }
+
+    fn resolve_path(&self, path: &str, follows: &mut u32) -> FsResult<INode> {
+        let mut current = if path.starts_with('/') {
+            self.inode_from_id(0, &self.fs.lock())
+        } else {
+            self.clone()
+        };
+        for name in path.split('/').filter(|name| {!name.is_empty()}) {
+            let next = current.find(name)?;
+            if !next.is_symlink() {
+                current = next;
+                continue;
+            }
+            *follows += 1;
+            if *follows > FOLLOW_LIMIT {
+                return Err(FsError::TooManyLinks);
+            }
+            // 相对路径的目标从符号链接所在的目录开始查找
+            current = current.resolve_path(&next.readlink()?, follows)?;
+        }
+        return Ok(current);
+    }
+
+    // inode编号
+    pub fn inode_id(&self) -> u32 {
+        return self.fs.lock().get_inode_id(self.block_id, self.block_offset);
+    }
+
+    pub fn is_dir(&self) -> bool {
+        return self.read_disk_inode(|disk_inode| {disk_inode.is_dir()});
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        return self.read_disk_inode(|disk_inode| {disk_inode.is_symlink()});
+    }
+
+    // 文件状态：inode编号、类型、大小、占用的块数、权限、所有者和时间戳
+    pub fn stat(&self) -> Stat {
+        let fs = self.fs.lock();
+        let inode = fs.get_inode_id(self.block_id, self.block_offset);
+        return self.read_disk_inode(|disk_inode| {
+            // 空洞不占用块，只统计实际引用的块
+            let (blocks, _) = disk_inode.referenced_blocks(|block_id| {fs.is_data_block(block_id)}, Arc::clone(&self.block_dev));
+            Stat {
+                inode,
+                _type: disk_inode._type,
+                size: disk_inode.size,
+                blocks: blocks.len() as u32,
+                mode: disk_inode.mode,
+                uid: disk_inode.uid,
+                gid: disk_inode.gid,
+                atime: disk_inode.atime,
+                mtime: disk_inode.mtime,
+                ctime: disk_inode.ctime,
+                nlink: disk_inode.link_count(),
+            }
+        });
+    }
+
+    // 修改权限位，只保留rwx位
+    pub fn chmod(&self, mode: u32) {
+        let fs = self.fs.lock();
+        fs.begin();
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.mode = mode & MODE_MASK;
+            disk_inode.ctime = fs.now();
+        });
+        fs.commit();
+    }
+
+    // 修改所有者
+    pub fn chown(&self, uid: u32, gid: u32) {
+        let fs = self.fs.lock();
+        fs.begin();
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.uid = uid;
+            disk_inode.gid = gid;
+            disk_inode.ctime = fs.now();
         });
+        fs.commit();
+    }
+
+    // inode所在文件系统的容量和空闲空间
+    pub fn statfs(&self) -> StatFs {
+        return self.fs.lock().statfs();
+    }
+
+    // 从文件系统找到inode id对应的inode块
+    fn inode_from_id(&self, inode_id: u32, fs: &FileSystem) -> INode {
+        let (block_id, _, block_offset) = fs.get_inode_block_id(inode_id);
+        return INode::new(block_id, block_offset, Arc::clone(&self.fs), Arc::clone(&self.block_dev));
     }
 
     // 找到以当前inode为目录下的文件的inode id
-    fn find_file_inode(&self, name: &str, disk_inode: &DiskINode) -> Option<u32> {
-        // 该目录下的文件总数
-        let file_count = disk_inode.size / DIR_SIZE;
-        for i in 0..file_count {
-            let mut dir = DirEntry::empty();
-            // 读取目录inode的目录项的文件名
-            disk_inode.read(i * DIR_SIZE, dir.to_bytes_mut(), Arc::clone(&self.block_dev));
-            if dir.name() == name {
-                return Some((&dir).inode_id());
+    fn find_file_inode(&self, name: &str, disk_inode: &DiskINode) -> FsResult<Option<u32>> {
+        return Ok(self.find_dir_entry(name, disk_inode)?.map(|record| {record.inode_id}));
+    }
+
+    // 找到目录下文件名为name的目录项
+    // 散列目录只需要读取文件名所在的桶，旧镜像中的线性目录需要扫描所有目录项
+    fn find_dir_entry(&self, name: &str, disk_inode: &DiskINode) -> FsResult<Option<DirRecord>> {
+        if disk_inode.is_hashed_dir() {
+            let buckets = disk_inode.size / BLOCK_SIZE as u32;
+            if buckets == 0 {
+                return Ok(None);
             }
+            let record = self.bucket_records(name_bucket(name, buckets), disk_inode)
+            .into_iter()
+            .find(|record| {record.name.as_deref() == Some(name)});
+            return Ok(record);
+        }
+        let mut found = None;
+        self.scan_records(disk_inode, |record| {
+            if record.name.as_deref() == Some(name) {
+                found = Some(record);
+                return false;
+            }
+            return true;
+        });
+        return Ok(found);
+    }
+
+    // 读取散列目录的一个桶，解析出桶中的目录项，槽序号为在整个目录中的序号
+    fn bucket_records(&self, bucket: u32, disk_inode: &DiskINode) -> Vec<DirRecord> {
+        let mut records = parse_entries(&self.read_bucket(bucket, disk_inode));
+        for record in records.iter_mut() {
+            record.slot += bucket * BUCKET_SLOTS;
+        }
+        return records;
+    }
+
+    // 按槽序号依次解析目录中的目录项并交给f处理，f返回false时停止
+    // 每次只读取一个块，大目录也不需要把全部数据放在堆上，跨越块边界的长文件名目录项与下一个块一起解析
+    fn scan_records(&self, disk_inode: &DiskINode, mut f: impl FnMut(DirRecord) -> bool) {
+        let mut data: Vec<u8> = Vec::new();
+        // data中第一个槽的序号
+        let mut base = 0;
+        let mut offset = 0;
+        while offset < disk_inode.size {
+            let len = (disk_inode.size - offset).min(BLOCK_SIZE as u32);
+            let start = data.len();
+            data.resize(start + len as usize, 0);
+            disk_inode.read(offset, &mut data[start..], Arc::clone(&self.block_dev));
+            offset += len;
+            let (records, parsed) = parse_entries_partial(&data, offset < disk_inode.size);
+            for mut record in records {
+                record.slot += base;
+                if !f(record) {
+                    return;
+                }
+            }
+            data.drain(..parsed);
+            base += parsed as u32 / DIR_SIZE;
         }
-        return None;
     }
 
     // 列举当前inode目录下的所有文件名
-    pub fn ls(&self) -> Vec<String> {
+    pub fn ls(&self) -> FsResult<Vec<String>> {
         let mut files = Vec::new();
         // 对disk inode互斥只读操作
         self.read_disk_inode(|disk_inode| {
-            assert!(disk_inode._type == INodeType::Directory);
-            let file_count = disk_inode.size / DIR_SIZE;
-            for i in 0..file_count {
-                let mut dir_entry = DirEntry::empty();
-                // 将磁盘缓存数据读取到dir entry
-                disk_inode.read(DIR_SIZE * i, dir_entry.to_bytes_mut(), Arc::clone(&self.block_dev));
-                files.push(String::from(dir_entry.name()));
+            if !disk_inode.is_dir() {
+                return Err(FsError::NotDir);
             }
-        });
-        return files;
+            // 已删除文件留下的空目录项和损坏的目录项不会列出
+            self.scan_records(disk_inode, |record| {
+                if let Some(name) = record.name {
+                    files.push(name);
+                }
+                return true;
+            });
+            return Ok(());
+        })?;
+        return Ok(files);
     }
 
     // 在当前目录下创建文件
-    pub fn create(&mut self, name: &str) -> Option<Arc<INode>> {
-        let (is_dir, file_exist) = self.read_disk_inode(|disk_inode| {
-            if disk_inode.is_dir() {
-                return (true, self.find_file_inode(name, disk_inode).is_some());
+    pub fn create(&self, name: &str) -> FsResult<Arc<INode>> {
+        return self.create_inode(name, INodeType::File, &[]);
+    }
+
+    // 在当前目录下创建子目录，子目录包含指向自身的"."和指向父目录的".."
+    pub fn mkdir(&self, name: &str) -> FsResult<Arc<INode>> {
+        return self.create_inode(name, INodeType::Directory, &[]);
+    }
+
+    // 在当前目录下创建指向target的符号链接，target保存在符号链接的数据块中
+    // target可以是不存在的路径，相对路径从符号链接所在的目录开始解析
+    pub fn symlink(&self, name: &str, target: &str) -> FsResult<Arc<INode>> {
+        if target.is_empty() {
+            return Err(FsError::InvalidName);
+        }
+        if target.len() > SYMLINK_TARGET_LIMIT {
+            return Err(FsError::NameTooLong);
+        }
+        return self.create_inode(name, INodeType::Symlink, target.as_bytes());
+    }
+
+    // 读取符号链接的目标路径
+    pub fn readlink(&self) -> FsResult<String> {
+        return self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_symlink() {
+                return Err(FsError::NotLink);
             }
-            return (false, false);
+            let mut data = vec![0u8; disk_inode.size as usize];
+            disk_inode.read(0, &mut data, Arc::clone(&self.block_dev));
+            return Ok(String::from_utf8_lossy(&data).into_owned());
         });
-        assert!(is_dir);
+    }
+
+    // 在当前目录下创建指定类型的inode，data为新inode的初始内容
+    fn create_inode(&self, name: &str, _type: INodeType, data: &[u8]) -> FsResult<Arc<INode>> {
+        check_name(name)?;
+        let mut fs = self.fs.lock();
+        let file_exist = self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_dir() {
+                return Err(FsError::NotDir);
+            }
+            return Ok(self.find_file_inode(name, disk_inode)?.is_some());
+        })?;
         if file_exist {
-            return None;
+            return Err(FsError::Exists);
         }
-        let mut fs = self.fs.lock();
-        let inode_seq = fs.alloc_inode();
+        self.reserve_dir_entry(name, &mut fs)?;
+        fs.begin();
+        let result = self.create_inode_locked(name, _type, data, &mut fs);
+        fs.commit();
+        return result;
+    }
 
+    // 分配并初始化新inode，添加到当前目录，空间不足时回收已经分配的inode和块
+    fn create_inode_locked(&self, name: &str, _type: INodeType, data: &[u8], fs: &mut MutexGuard<FileSystem>) -> FsResult<Arc<INode>> {
+        let inode_seq = fs.alloc_inode()?;
         let (block_id, _, block_offset) = fs.get_inode_block_id(inode_seq);
+        let now = fs.now();
         // 初始化新文件的磁盘inode
         get_block_cache(block_id as usize, Arc::clone(&self.block_dev))
         .lock()
         .modify(block_offset as usize, |disk_inode: &mut DiskINode| {
-            disk_inode.init(INodeType::File);
+            disk_inode.init(_type, now);
         });
+        let inode = Self::new(block_id, block_offset, Arc::clone(&self.fs), Arc::clone(&self.block_dev));
+        let mut result = Ok(());
+        if _type == INodeType::Directory {
+            // 新建的目录都是散列目录
+            inode.modify_disk_inode(|disk_inode| {disk_inode.flags |= FLAG_HASHED_DIR});
+            let parent_id = fs.get_inode_id(self.block_id, self.block_offset);
+            result = inode.add_dir_entry(".", inode_seq, fs)
+            .and_then(|_| {inode.add_dir_entry("..", parent_id, fs)});
+        }
+        // 写入初始内容
+        if result.is_ok() && !data.is_empty() {
+            result = inode.modify_disk_inode(|disk_inode| {
+                inode.alloc_range(0, data.len() as u32, disk_inode, fs)?;
+                disk_inode.write(0, data, Arc::clone(&self.block_dev));
+                return Ok(());
+            });
+        }
         // 在当前目录inode中添加新文件的目录项
+        if result.is_ok() {
+            result = self.add_dir_entry(name, inode_seq, fs);
+        }
+        if let Err(err) = result {
+            inode.shrink(0, fs);
+            fs.dealloc_inode(inode_seq);
+            return Err(err);
+        }
+        return Ok(Arc::new(inode));
+    }
+
+    // 在当前目录inode中添加一个目录项，并更新目录的修改时间
+    // 散列目录将目录项放入文件名所在的桶，线性目录添加在末尾，长文件名的目录项占用多个连续的槽
+    fn add_dir_entry(&self, name: &str, inode_id: u32, fs: &mut MutexGuard<FileSystem>) -> FsResult<()> {
+        let now = fs.now();
+        let dir_entry = encode_entry(name, inode_id)?;
+        return self.modify_disk_inode(|dir_inode| {
+            let offset = if dir_inode.is_hashed_dir() {
+                self.hashed_entry_offset(name, dir_entry.len() as u32 / DIR_SIZE, dir_inode, fs)?
+            } else {
+                None
+            };
+            let offset = match offset {
+                Some(offset) => offset,
+                None => {
+                    // 线性目录优先复用已删除文件留下的连续空槽，没有足够的空槽时目录扩容后写在末尾
+                    let slots = dir_entry.len() as u32 / DIR_SIZE;
+                    let mut slot = 0;
+                    self.scan_records(dir_inode, |record| {
+                        if record.slot >= slot + slots {
+                            return false;
+                        }
+                        slot = record.slot + record.slots;
+                        return true;
+                    });
+                    self.alloc_range(slot * DIR_SIZE, slots * DIR_SIZE, dir_inode, fs)?;
+                    slot * DIR_SIZE
+                }
+            };
+            dir_inode.write(offset, &dir_entry, Arc::clone(&self.block_dev));
+            dir_inode.touch(now);
+            return Ok(());
+        });
+    }
+
+    // 在散列目录中为文件名找到slots个连续的空槽，返回目录项的偏移
+    // 文件名所在的桶已满时逐个分裂桶，文件名所在的桶分裂后仍放不下或桶数达到MAX_BUCKETS时，目录退化为线性目录并返回None
+    // 调用者应在事务开始前调用reserve_dir_entry，事务中最多只需要为空目录分配第一个桶
+    fn hashed_entry_offset(&self, name: &str, slots: u32, dir_inode: &mut DiskINode, fs: &mut MutexGuard<FileSystem>) -> FsResult<Option<u32>> {
+        let mut name_bucket_split = false;
+        loop {
+            if let Some(offset) = self.bucket_free_offset(name, slots, dir_inode) {
+                return Ok(Some(offset));
+            }
+            let buckets = dir_inode.size / BLOCK_SIZE as u32;
+            if name_bucket_split || buckets >= MAX_BUCKETS {
+                dir_inode.flags &= !FLAG_HASHED_DIR;
+                return Ok(None);
+            }
+            self.split_bucket(dir_inode, fs)?;
+            name_bucket_split = splits_name_bucket(name, buckets);
+        }
+    }
+
+    // 文件名所在的桶中slots个连续空槽的偏移，没有桶或桶已满时返回None
+    fn bucket_free_offset(&self, name: &str, slots: u32, dir_inode: &DiskINode) -> Option<u32> {
+        let buckets = dir_inode.size / BLOCK_SIZE as u32;
+        if buckets == 0 {
+            return None;
+        }
+        let bucket = name_bucket(name, buckets);
+        let records = parse_entries(&self.read_bucket(bucket, dir_inode));
+        return find_free_slots(&records, BUCKET_SLOTS, slots)
+        .map(|slot| {bucket * BLOCK_SIZE as u32 + slot * DIR_SIZE});
+    }
+
+    // 在事务开始前为name在散列目录中预留空槽，文件名所在的桶已满时逐个分裂桶，每次分裂是一个单独的事务
+    // 分裂前后目录都是完整的，之后添加目录项的事务不再需要分裂，每个事务修改的块数都有上限
+    // 文件名所在的桶分裂后仍然放不下时，桶中的文件名散列值的低位相同，继续分裂也无法腾出空槽，目录退化为线性目录
+    fn reserve_dir_entry(&self, name: &str, fs: &mut MutexGuard<FileSystem>) -> FsResult<()> {
+        let slots = slots_for_name(name.len());
+        let mut name_bucket_split = false;
+        loop {
+            let (full, buckets) = self.read_disk_inode(|dir_inode| {
+                let full = dir_inode.is_hashed_dir() && self.bucket_free_offset(name, slots, dir_inode).is_none();
+                (full, dir_inode.size / BLOCK_SIZE as u32)
+            });
+            if !full || buckets >= MAX_BUCKETS {
+                return Ok(());
+            }
+            fs.begin();
+            let result = self.modify_disk_inode(|dir_inode| {
+                if name_bucket_split {
+                    dir_inode.flags &= !FLAG_HASHED_DIR;
+                    return Ok(());
+                }
+                self.split_bucket(dir_inode, fs)
+            });
+            fs.commit();
+            result?;
+            name_bucket_split = splits_name_bucket(name, buckets);
+        }
+    }
+
+    // 读取散列目录一个桶的数据
+    fn read_bucket(&self, bucket: u32, dir_inode: &DiskINode) -> Vec<u8> {
+        let mut data = vec![0u8; BLOCK_SIZE];
+        dir_inode.read(bucket * BLOCK_SIZE as u32, &mut data, Arc::clone(&self.block_dev));
+        return data;
+    }
+
+    // 线性散列分裂一个桶：在目录末尾新增一个桶，将被分裂的桶中按新的桶数属于新桶的目录项移过去，空目录扩大到一个桶
+    // 只读写被分裂的桶和新桶两个块，无法计算散列值的损坏目录项留在原来的桶中
+    fn split_bucket(&self, dir_inode: &mut DiskINode, fs: &mut MutexGuard<FileSystem>) -> FsResult<()> {
+        let buckets = dir_inode.size / BLOCK_SIZE as u32;
+        self.alloc_range(buckets * BLOCK_SIZE as u32, BLOCK_SIZE as u32, dir_inode, fs)?;
+        if buckets == 0 {
+            return Ok(());
+        }
+        let source = split_source(buckets);
+        let data = self.read_bucket(source, dir_inode);
+        let mut kept = vec![0u8; BLOCK_SIZE];
+        let mut moved = vec![0u8; BLOCK_SIZE];
+        let (mut kept_slots, mut moved_slots) = (0, 0);
+        for record in parse_entries(&data) {
+            let to_new = match record.name {
+                Some(ref name) => name_bucket(name, buckets + 1) == buckets,
+                None => false,
+            };
+            let (dst, next) = if to_new {(&mut moved, &mut moved_slots)} else {(&mut kept, &mut kept_slots)};
+            let src = (record.slot * DIR_SIZE) as usize;
+            let len = (record.slots * DIR_SIZE) as usize;
+            let offset = (*next * DIR_SIZE) as usize;
+            dst[offset..offset + len].copy_from_slice(&data[src..src + len]);
+            *next += record.slots;
+        }
+        dir_inode.write(source * BLOCK_SIZE as u32, &kept, Arc::clone(&self.block_dev));
+        dir_inode.write(buckets * BLOCK_SIZE as u32, &moved, Arc::clone(&self.block_dev));
+        return Ok(());
+    }
+
+    // 在当前目录下创建指向target的硬链接，target不能是目录
+    pub fn link(&self, name: &str, target: &INode) -> FsResult<()> {
+        check_name(name)?;
+        if target.is_dir() {
+            return Err(FsError::IsDir);
+        }
+        let mut fs = self.fs.lock();
+        let file_exist = self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_dir() {
+                return Err(FsError::NotDir);
+            }
+            return Ok(self.find_file_inode(name, disk_inode)?.is_some());
+        })?;
+        if file_exist {
+            return Err(FsError::Exists);
+        }
+        let target_id = fs.get_inode_id(target.block_id, target.block_offset);
+        let now = fs.now();
+        self.reserve_dir_entry(name, &mut fs)?;
+        // 添加目录项和增加链接数在同一个事务中完成
+        fs.begin();
+        let result = self.add_dir_entry(name, target_id, &mut fs);
+        if result.is_ok() {
+            target.modify_disk_inode(|disk_inode| {
+                disk_inode.nlink = disk_inode.link_count() + 1;
+                disk_inode.ctime = now;
+            });
+        }
+        fs.commit();
+        return result;
+    }
+
+    // 删除当前目录下的文件，链接数减为0时回收文件的数据块、索引块和inode
+    pub fn unlink(&self, name: &str) -> FsResult<()> {
+        // "."和".."不能删除
+        if name == "." || name == ".." {
+            return Err(FsError::InvalidName);
+        }
+        let mut fs = self.fs.lock();
+        let record = self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_dir() {
+                return Err(FsError::NotDir);
+            }
+            self.find_dir_entry(name, disk_inode)?.ok_or(FsError::NotFound)
+        })?;
+        let inode_id = record.inode_id;
+        // 目录只有为空时才能删除
+        let inode = self.inode_from_id(inode_id, &fs);
+        if inode.is_dir() && inode.ls()?.iter().any(|name| {name != "." && name != ".."}) {
+            return Err(FsError::NotEmpty);
+        }
+        // 先置空目录项再回收数据块和inode，大文件分多个事务回收，崩溃后不会留下指向半截文件的目录项
+        fs.begin();
+        self.clear_dir_record(&record, &mut fs);
+        inode.drop_link(inode_id, &mut fs);
+        fs.commit();
+        self.merge_buckets(&mut fs);
+        return Ok(());
+    }
+
+    // 将当前目录下的old_name移动到new_dir目录下并命名为new_name，new_name已存在时替换原有的文件
+    // 所有修改在同一个事务中完成，崩溃后只会看到移动前或移动后的状态，可以先写临时文件再替换目标文件
+    // 目录只能替换空目录，文件不能替换目录，目录也不能移动到自身或自身的子目录中
+    pub fn rename(&self, old_name: &str, new_dir: &INode, new_name: &str) -> FsResult<()> {
+        check_name(old_name)?;
+        check_name(new_name)?;
+        let mut fs = self.fs.lock();
+        let inode_id = self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_dir() {
+                return Err(FsError::NotDir);
+            }
+            self.find_file_inode(old_name, disk_inode)?.ok_or(FsError::NotFound)
+        })?;
+        let target = new_dir.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_dir() {
+                return Err(FsError::NotDir);
+            }
+            new_dir.find_dir_entry(new_name, disk_inode)
+        })?;
+        let inode = self.inode_from_id(inode_id, &fs);
+        let is_dir = inode.is_dir();
+        let dir_id = fs.get_inode_id(self.block_id, self.block_offset);
+        let new_dir_id = fs.get_inode_id(new_dir.block_id, new_dir.block_offset);
+        if is_dir && self.is_subdir(new_dir_id, inode_id, &fs) {
+            return Err(FsError::InvalidArgument);
+        }
+        if let Some(ref target) = target {
+            // 新旧名字指向同一个inode，不做任何修改
+            if target.inode_id == inode_id {
+                return Ok(());
+            }
+            let target_inode = self.inode_from_id(target.inode_id, &fs);
+            match (is_dir, target_inode.is_dir()) {
+                (true, false) => return Err(FsError::NotDir),
+                (false, true) => return Err(FsError::IsDir),
+                (true, true) if target_inode.ls()?.iter().any(|name| {name != "." && name != ".."}) => {
+                    return Err(FsError::NotEmpty);
+                },
+                _ => {},
+            }
+        }
+        if target.is_none() {
+            new_dir.reserve_dir_entry(new_name, &mut fs)?;
+        }
+        fs.begin();
+        let now = fs.now();
+        let mut replaced = None;
+        let result = match target {
+            // 目标已存在，直接将目标目录项指向新inode，被替换的inode在其他修改完成后再减少链接数
+            Some(target) => {
+                new_dir.set_entry_inode(&target, inode_id, now);
+                replaced = Some(target.inode_id);
+                Ok(())
+            },
+            None => new_dir.add_dir_entry(new_name, inode_id, &mut fs),
+        };
+        if result.is_ok() {
+            // 预留空槽时分裂桶可能移动了旧目录项，需要重新查找
+            let record = self.read_disk_inode(|disk_inode| {self.find_dir_entry(old_name, disk_inode)});
+            if let Ok(Some(record)) = record {
+                self.clear_dir_record(&record, &mut fs);
+            }
+            // 移动到其他目录的子目录，".."指向新的父目录
+            if is_dir && dir_id != new_dir_id {
+                let parent = inode.read_disk_inode(|disk_inode| {inode.find_dir_entry("..", disk_inode)});
+                if let Ok(Some(parent)) = parent {
+                    inode.set_entry_inode(&parent, new_dir_id, now);
+                }
+            }
+            inode.modify_disk_inode(|disk_inode| {disk_inode.ctime = now});
+        }
+        // 回收大文件时会分多个事务提交，放在最后保证移动本身在第一个事务中完成
+        if let Some(replaced) = replaced {
+            self.inode_from_id(replaced, &fs).drop_link(replaced, &mut fs);
+        }
+        fs.commit();
+        if result.is_ok() {
+            self.merge_buckets(&mut fs);
+        }
+        return result;
+    }
+
+    // dir_id是否为inode_id自身或它的子目录，沿".."向上查找直到根目录
+    fn is_subdir(&self, dir_id: u32, inode_id: u32, fs: &FileSystem) -> bool {
+        let mut current = dir_id;
+        // 最多查找inode数量次，避免损坏镜像中".."形成的环
+        for _ in 0..fs.inode_bitmap.bits() {
+            if current == inode_id {
+                return true;
+            }
+            if current == 0 {
+                return false;
+            }
+            let dir = self.inode_from_id(current, fs);
+            match dir.read_disk_inode(|disk_inode| {dir.find_file_inode("..", disk_inode)}) {
+                Ok(Some(parent)) => current = parent,
+                _ => return false,
+            }
+        }
+        return false;
+    }
+
+    // 减少inode的链接数，没有其他目录项指向该inode时回收文件占用的数据块、索引块和inode
+    fn drop_link(&self, inode_id: u32, fs: &mut MutexGuard<FileSystem>) {
+        let now = fs.now();
+        let nlink = self.modify_disk_inode(|disk_inode| {
+            disk_inode.nlink = disk_inode.link_count() - 1;
+            disk_inode.ctime = now;
+            disk_inode.nlink
+        });
+        if nlink == 0 {
+            self.shrink(0, fs);
+            fs.dealloc_inode(inode_id);
+        }
+    }
+
+    // 将目录项占用的所有槽置空，并更新目录的修改时间，空槽可以被之后添加的目录项复用
+    // 线性目录末尾的目录项都为空时缩小目录，回收末尾不再使用的块，散列目录在事务提交后由merge_buckets缩小
+    fn clear_dir_record(&self, record: &DirRecord, fs: &mut MutexGuard<FileSystem>) {
+        let now = fs.now();
+        let empty = vec![0u8; (record.slots * DIR_SIZE) as usize];
+        let new_size = self.modify_disk_inode(|dir_inode| {
+            dir_inode.write(record.slot * DIR_SIZE, &empty, Arc::clone(&self.block_dev));
+            dir_inode.touch(now);
+            // 只有删除的是最后一个目录项时才需要缩小
+            if dir_inode.is_hashed_dir() || (record.slot + record.slots) * DIR_SIZE < dir_inode.size {
+                return None;
+            }
+            let mut end = 0;
+            self.scan_records(dir_inode, |record| {
+                end = (record.slot + record.slots) * DIR_SIZE;
+                return true;
+            });
+            return Some(end);
+        });
+        if let Some(new_size) = new_size {
+            self.shrink(new_size, fs);
+        }
+    }
+
+    // 散列目录的最后一个桶与它分裂前所在的桶合计不超过半个桶时，将最后一个桶合并回去并回收该块，直到不能再合并
+    // 每次合并是一个单独的事务，调用者需持有文件系统的锁且不在事务中；只合并到半个桶，避免之后添加目录项时立即再次分裂
+    fn merge_buckets(&self, fs: &mut MutexGuard<FileSystem>) {
+        loop {
+            let merged = self.read_disk_inode(|dir_inode| {self.merged_last_bucket(dir_inode)});
+            let (target, data) = match merged {
+                Some(merged) => merged,
+                None => return,
+            };
+            fs.begin();
+            let freed = self.modify_disk_inode(|dir_inode| {
+                dir_inode.write(target * BLOCK_SIZE as u32, &data, Arc::clone(&self.block_dev));
+                dir_inode.decrease_size(dir_inode.size - BLOCK_SIZE as u32, Arc::clone(&self.block_dev))
+            });
+            for block_id in freed {
+                fs.dealloc_data_block(block_id);
+            }
+            fs.commit();
+        }
+    }
+
+    // 最后一个桶可以合并时，返回合并到的桶和合并后该桶的数据
+    fn merged_last_bucket(&self, dir_inode: &DiskINode) -> Option<(u32, Vec<u8>)> {
+        let buckets = dir_inode.size / BLOCK_SIZE as u32;
+        if !dir_inode.is_hashed_dir() || buckets <= 1 {
+            return None;
+        }
+        let target = split_source(buckets - 1);
+        let target_data = self.read_bucket(target, dir_inode);
+        let last_data = self.read_bucket(buckets - 1, dir_inode);
+        let target_records = parse_entries(&target_data);
+        let last_records = parse_entries(&last_data);
+        let used: u32 = target_records.iter().chain(last_records.iter()).map(|record| {record.slots}).sum();
+        if used > BUCKET_SLOTS / 2 {
+            return None;
+        }
+        // 两个桶的目录项依次紧凑排列，损坏的目录项一起保留
+        let mut data = vec![0u8; BLOCK_SIZE];
+        let mut next = 0;
+        for (records, src) in [(&target_records, &target_data), (&last_records, &last_data)] {
+            for record in records.iter() {
+                let from = (record.slot * DIR_SIZE) as usize;
+                let len = (record.slots * DIR_SIZE) as usize;
+                let to = (next * DIR_SIZE) as usize;
+                data[to..to + len].copy_from_slice(&src[from..from + len]);
+                next += record.slots;
+            }
+        }
+        return Some((target, data));
+    }
+
+    // 将目录项指向另一个inode，并更新目录的修改时间
+    fn set_entry_inode(&self, record: &DirRecord, inode_id: u32, now: u32) {
         self.modify_disk_inode(|dir_inode| {
-            // 计算新目录项的偏移
-            let offset = dir_inode.size;
-            // 目录inode块扩容
-            self.increase_size(dir_inode.size + DIR_SIZE, dir_inode, &mut fs);
-            // 写入目录entry
-            let dir_entry = DirEntry::new(name, inode_seq);
-            dir_inode.write(offset, dir_entry.to_bytes(), Arc::clone(&self.block_dev));
+            dir_inode.write(record.slot * DIR_SIZE + INODE_ID_OFFSET, &inode_id.to_le_bytes(), Arc::clone(&self.block_dev));
+            dir_inode.touch(now);
         });
-        let inode = Self::new(block_id, block_offset, Arc::clone(&self.fs), Arc::clone(&self.block_dev));
-        return Some(Arc::new(inode));
     }
 
-    // 从inode读取文件
-    pub fn read_at(&self, offset: u32, buf: &mut [u8]) {
+    // 从inode的offset位置读取文件，返回读取的字节数，到达文件末尾时返回0
+    // 读取不更新atime，避免只读操作也产生需要写回的修改
+    pub fn read_at(&self, offset: u32, buf: &mut [u8]) -> usize {
         // 互斥读
         let _fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode: &DiskINode| {
-            disk_inode.read(offset, buf, Arc::clone(&self.block_dev));
+        return self.read_disk_inode(|disk_inode: &DiskINode| {
+            disk_inode.read(offset, buf, Arc::clone(&self.block_dev))
         });
     }
 
-    // 写入文件offset位置
-    pub fn write_at(&mut self, offset: u32, buf: &[u8]) {
+    // 写入文件offset位置，返回写入的字节数，并更新文件的修改时间
+    // 写入位置超过文件末尾时，中间的部分是空洞，不分配块
+    // 空间不足时返回已经写入的字节数，一个字节都没有写入时返回NoSpace
+    pub fn write_at(&self, offset: u32, buf: &[u8]) -> FsResult<usize> {
+        // 目录的内容只能通过目录项操作修改
+        if self.is_dir() {
+            return Err(FsError::IsDir);
+        }
+        // 文件大小不能超过4GiB
+        if offset as u64 + buf.len() as u64 > u32::MAX as u64 {
+            return Err(FsError::OutOfRange);
+        }
         // 互斥写
         let mut fs = self.fs.lock();
-        self.modify_disk_inode(|disk_inode: &mut DiskINode| {
-            self.increase_size(offset + buf.len() as u32, disk_inode, &mut fs);
-            disk_inode.write(offset, buf, Arc::clone(&self.block_dev));
+        let now = fs.now();
+        let mut written = 0;
+        // 分段写入，每段一个事务，保证一个事务修改的块不超过日志容量
+        for chunk in buf.chunks(WRITE_CHUNK_SIZE) {
+            let offset = offset + written as u32;
+            fs.begin();
+            let result = self.modify_disk_inode(|disk_inode: &mut DiskINode| {
+                self.alloc_range(offset, chunk.len() as u32, disk_inode, &mut fs)?;
+                disk_inode.write(offset, chunk, Arc::clone(&self.block_dev));
+                disk_inode.touch(now);
+                return Ok(());
+            });
+            fs.commit();
+            if let Err(err) = result {
+                if written == 0 {
+                    return Err(err);
+                }
+                break;
+            }
+            written += chunk.len();
+        }
+        return Ok(written);
+    }
+
+    // 将文件写回块设备
+    // 文件的数据块依赖bitmap、目录项等元数据才能被找到，所以写回整个文件系统的脏块
+    pub fn fsync(&self) {
+        self.fs.lock().sync();
+    }
+
+    // 文件大小
+    pub fn size(&self) -> u32 {
+        return self.read_disk_inode(|disk_inode| {disk_inode.size});
+    }
+
+    // 清空文件内容，回收文件占用的数据块和索引块
+    pub fn clear(&self) -> FsResult<()> {
+        // 目录的数据块保存目录项，不能被清空
+        if self.is_dir() {
+            return Err(FsError::IsDir);
+        }
+        let mut fs = self.fs.lock();
+        fs.begin();
+        self.shrink(0, &mut fs);
+        let now = fs.now();
+        self.modify_disk_inode(|disk_inode| {disk_inode.touch(now)});
+        fs.commit();
+        return Ok(());
+    }
+
+    // 调整文件大小，缩小时回收多余的数据块和索引块，增大时新增的部分是空洞，读出为0
+    pub fn truncate(&self, new_size: u32) -> FsResult<()> {
+        if self.is_dir() {
+            return Err(FsError::IsDir);
+        }
+        let mut fs = self.fs.lock();
+        fs.begin();
+        let now = fs.now();
+        self.modify_disk_inode(|disk_inode| {
+            self.increase_size(new_size, disk_inode);
+            disk_inode.touch(now);
         });
+        self.shrink(new_size, &mut fs);
+        fs.commit();
+        return Ok(());
     }
-    // inode对应的文件扩容到新的大小
-    fn increase_size(&self, new_size: u32, disk_inode: &mut DiskINode, fs: &mut MutexGuard<FileSystem>) {
+
+    // 缩小文件到new_size，回收多余的数据块和索引块，调用者需持有文件系统的锁并已开始事务
+    // 大文件分多次缩小，每次最多回收SHRINK_CHUNK_BLOCKS个数据块，之后提交事务再开始新的事务，保证事务不超过日志容量
+    // 调用者在缩小前应完成其他修改，中途崩溃时只会留下一个较小的文件
+    fn shrink(&self, new_size: u32, fs: &mut MutexGuard<FileSystem>) {
+        loop {
+            let (freed, done) = self.modify_disk_inode(|disk_inode| {
+                if new_size >= disk_inode.size {
+                    return (Vec::new(), true);
+                }
+                let step_size = disk_inode.data_blocks().saturating_sub(SHRINK_CHUNK_BLOCKS) * BLOCK_SIZE as u32;
+                let size = new_size.max(step_size);
+                return (disk_inode.decrease_size(size, Arc::clone(&self.block_dev)), size == new_size);
+            });
+            for block_id in freed {
+                fs.dealloc_data_block(block_id);
+            }
+            if done {
+                break;
+            }
+            fs.commit();
+            fs.begin();
+        }
+    }
+
+    // 扩大文件到new_size，新增的范围是空洞，不分配块
+    fn increase_size(&self, new_size: u32, disk_inode: &mut DiskINode) {
+        if new_size > disk_inode.size {
+            disk_inode.size = new_size;
+        }
+    }
+
+    // 为写入文件[offset, offset + len)范围分配块：文件扩大到范围末尾，为范围内的空洞分配数据块和缺少的索引块
+    // 空间不足时文件恢复原来的大小，回收原大小之后已经分配的块
+    fn alloc_range(&self, offset: u32, len: u32, disk_inode: &mut DiskINode, fs: &mut MutexGuard<FileSystem>) -> FsResult<()> {
         let old_size = disk_inode.size;
-        // 分配需要的新data blocks
-        let new_blocks_needed = DiskINode::data_blocks_for_size(new_size - old_size);
-        let mut new_blocks: Vec<u32> = Vec::new();
-        for _ in 0..new_blocks_needed {
-            new_blocks.push(fs.alloc_data_block());
-        }
-        // 分配新的索引blocks
-        let mut index_blocks: Vec<u32> = Vec::new();
-        // 所需的新索引块 = 新大小索引总数 - 旧索引总数
-        let index_blocks_needed = DiskINode::index_blocks_for_size(new_size) - DiskINode::index_blocks_for_size(old_size);
-        for _ in 0..index_blocks_needed {
-            index_blocks.push(fs.alloc_data_block());
-        }
-        // 磁盘inode扩容
-        disk_inode.increse_size(new_size, new_blocks, index_blocks, Arc::clone(&self.block_dev));
+        self.increase_size(offset + len, disk_inode);
+        let result = self.fill_holes(offset, len, disk_inode, fs);
+        if result.is_err() {
+            for block_id in disk_inode.decrease_size(old_size, Arc::clone(&self.block_dev)) {
+                fs.dealloc_data_block(block_id);
+            }
+        }
+        return result;
+    }
+
+    // 为[offset, offset + len)范围内的空洞分配数据块，新分配的块已经清零
+    fn fill_holes(&self, offset: u32, len: u32, disk_inode: &mut DiskINode, fs: &mut MutexGuard<FileSystem>) -> FsResult<()> {
+        if len == 0 {
+            return Ok(());
+        }
+        let start = offset / BLOCK_SIZE as u32;
+        let end = DiskINode::data_blocks_for_size(offset + len);
+        for seq in start..end {
+            if disk_inode.get_block_id(seq, Arc::clone(&self.block_dev)) != 0 {
+                continue;
+            }
+            // 先分配数据块，再分配缺少的索引块
+            let mut new_blocks: Vec<u32> = Vec::new();
+            for _ in 0..1 + disk_inode.missing_index_blocks(seq, Arc::clone(&self.block_dev)) {
+                match fs.alloc_data_block() {
+                    Ok(block_id) => new_blocks.push(block_id),
+                    Err(err) => {
+                        for block_id in new_blocks {
+                            fs.dealloc_data_block(block_id);
+                        }
+                        return Err(err);
+                    }
+                }
+            }
+            let mut index_blocks = new_blocks.split_off(1);
+            disk_inode.set_block_id(seq, new_blocks[0], &mut index_blocks, Arc::clone(&self.block_dev));
+        }
+        return Ok(());
     }
 }
 
+// 文件名不能为空，不能包含路径分隔符，也不能是"."和".."，超过255字节时返回NameTooLong
+fn check_name(name: &str) -> FsResult<()> {
+    if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+        return Err(FsError::InvalidName);
+    }
+    if name.len() > NAME_LIMIT {
+        return Err(FsError::NameTooLong);
+    }
+    return Ok(());
+}
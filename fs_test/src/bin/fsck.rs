@@ -0,0 +1,51 @@
+use fs::block_device::BlockFile;
+use fs::fs::FileSystem;
+use fs::fsck::fsck;
+use std::fs::OpenOptions;
+use std::process::exit;
+use std::sync::Arc;
+
+fn usage() -> ! {
+    eprintln!("usage: fsck [-f] <image>");
+    eprintln!("  -f, --fix   fix the problems found in the image");
+    exit(1);
+}
+
+fn main() {
+    let mut fix = false;
+    let mut image = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "-f" | "--fix" => fix = true,
+            "-h" | "--help" => usage(),
+            _ if image.is_none() => image = Some(arg),
+            _ => usage(),
+        }
+    }
+    let image = image.unwrap_or_else(|| usage());
+    // 打开文件系统时会重放日志，所以总是以可写方式打开镜像
+    let file = OpenOptions::new()
+    .read(true)
+    .write(true)
+    .open(&image)
+    .unwrap_or_else(|e| {
+        eprintln!("cannot open image {}: {}", image, e);
+        exit(1);
+    });
+    let fs = FileSystem::open(Arc::new(BlockFile::new(file))).unwrap_or_else(|e| {
+        eprintln!("cannot open file system in {}: {}", image, e);
+        exit(1);
+    });
+    let problems = fsck(&fs, fix);
+    for problem in problems.iter() {
+        println!("{}", problem);
+    }
+    if problems.is_empty() {
+        println!("{}: clean", image);
+    }else if fix {
+        println!("{}: fixed {} problems", image, problems.len());
+    }else {
+        println!("{}: {} problems found, run with -f to fix", image, problems.len());
+        exit(1);
+    }
+}
@@ -1,48 +1,178 @@
-use fs::block_device::BlockDevice;
+use fs::block_device::BlockFile;
 use fs::block_cache::BLOCK_SIZE;
+use fs::dir::NAME_LIMIT;
 use fs::fs::FileSystem;
-use std::io::{Seek, SeekFrom, Read, Write};
-use std::sync::Mutex;
-use std::fs::{File,OpenOptions};
-use alloc::sync::Arc;
-extern crate alloc;
-struct BlockFile(Mutex<File>);
-
-
-impl BlockDevice for BlockFile {
-    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
-        let mut file = self.0.lock().unwrap();
-        file.seek(SeekFrom::Start((block_id * BLOCK_SIZE) as u64)).expect("file seek failed");
-        assert_eq!(file.read(buf).unwrap(), BLOCK_SIZE, "NOT a complete block");
+use fs::error::FsError;
+use fs::vfs::INode;
+use std::fs::{OpenOptions, read_dir};
+use std::path::PathBuf;
+use std::process::exit;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 默认打包user_lib编译出的应用程序
+const DEFAULT_SOURCE: &str = "../user_lib/target/riscv64gc-unknown-none-elf/release/";
+const DEFAULT_OUTPUT: &str = "target/fs.img";
+// 默认镜像大小 4096个块，即16MiB
+const DEFAULT_TOTAL_BLOCKS: u32 = 4096;
+const DEFAULT_INODE_BITMAP_BLOCKS: u32 = 1;
+const ELF_MAGIC: [u8; 4] = [0x7f, 0x45, 0x4c, 0x46];
+
+// 命令行参数
+struct Args {
+    sources: Vec<(PathBuf, String)>, // 需要打包的目录，以及放入镜像中的目录
+    output: PathBuf,          // 输出的镜像文件
+    total_blocks: u32,        // 镜像总块数
+    inode_bitmap_blocks: u32, // inode bitmap块数
+}
+
+fn usage() -> ! {
+    eprintln!("usage: fs_test [-s <dir>[:<image dir>]]... [-o <image>] [-b <total blocks>] [-i <inode bitmap blocks>]");
+    eprintln!("  -s, --source   directory whose ELF files are packed into <image dir> (default /), can repeat (default: {})", DEFAULT_SOURCE);
+    eprintln!("  -o, --output   output image path (default: {})", DEFAULT_OUTPUT);
+    eprintln!("  -b, --blocks   total {} bytes blocks of the image (default: {})", BLOCK_SIZE, DEFAULT_TOTAL_BLOCKS);
+    eprintln!("  -i, --inode-bitmap-blocks   inode bitmap blocks, each indexes {} inodes (default: {})", BLOCK_SIZE * 8, DEFAULT_INODE_BITMAP_BLOCKS);
+    exit(1);
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        sources: Vec::new(),
+        output: PathBuf::from(DEFAULT_OUTPUT),
+        total_blocks: DEFAULT_TOTAL_BLOCKS,
+        inode_bitmap_blocks: DEFAULT_INODE_BITMAP_BLOCKS,
+    };
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        // 每个选项都需要一个参数值
+        let value = match arg.as_str() {
+            "-h" | "--help" => usage(),
+            _ => iter.next().unwrap_or_else(|| usage()),
+        };
+        match arg.as_str() {
+            "-s" | "--source" => args.sources.push(parse_source(&value)),
+            "-o" | "--output" => args.output = PathBuf::from(value),
+            "-b" | "--blocks" => args.total_blocks = value.parse().unwrap_or_else(|_| usage()),
+            "-i" | "--inode-bitmap-blocks" => args.inode_bitmap_blocks = value.parse().unwrap_or_else(|_| usage()),
+            _ => usage(),
+        }
+    }
+    if args.sources.is_empty() {
+        args.sources.push(parse_source(DEFAULT_SOURCE));
     }
+    args
+}
 
-    fn write_block(&self, block_id: usize, buf: &[u8]) {
-        let mut file = self.0.lock().unwrap();
-        file.seek(SeekFrom::Start((block_id * BLOCK_SIZE) as u64)).expect("file seek failed");
-        assert_eq!(file.write(buf).unwrap(), BLOCK_SIZE, "NOT a complete block");
+// 解析 <dir>[:<image dir>] 形式的参数
+fn parse_source(value: &str) -> (PathBuf, String) {
+    match value.split_once(':') {
+        Some((dir, image_dir)) => (PathBuf::from(dir), String::from(image_dir)),
+        None => (PathBuf::from(value), String::from("/")),
     }
 }
 
+// 文件系统时钟，使用主机的UNIX时间
+fn host_clock() -> u32 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs() as u32)
+}
+
+// 在镜像中逐级查找或创建目录
+fn make_dirs(root: &INode, path: &str) -> INode {
+    let mut current = root.clone();
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        current = match current.find(name) {
+            Ok(dir) if dir.is_dir() => dir,
+            Ok(_) => {
+                eprintln!("cannot create directory {}: a file with the same name exists", path);
+                exit(1);
+            }
+            Err(_) => match current.mkdir(name) {
+                Ok(dir) => dir.as_ref().clone(),
+                Err(e) => {
+                    eprintln!("cannot create directory {}: {}", path, e);
+                    exit(1);
+                }
+            },
+        };
+    }
+    current
+}
+
+// 收集目录下所有的ELF文件，返回 (文件名, 文件数据)
+fn collect_elf_files(dir: &PathBuf) -> Vec<(String, Vec<u8>)> {
+    let mut files = Vec::new();
+    let entries = read_dir(dir).unwrap_or_else(|e| {
+        eprintln!("cannot read source directory {}: {}", dir.display(), e);
+        exit(1);
+    });
+    for entry in entries {
+        let entry = entry.unwrap();
+        if !entry.file_type().unwrap().is_file() {
+            continue;
+        }
+        let data = std::fs::read(entry.path()).unwrap();
+        if data.len() < ELF_MAGIC.len() || data[..ELF_MAGIC.len()] != ELF_MAGIC {
+            continue;
+        }
+        files.push((entry.file_name().into_string().unwrap(), data));
+    }
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    files
+}
+
 fn main() {
-    let block_file = Arc::new(BlockFile(Mutex::new({
+    let args = parse_args();
+    let block_file = Arc::new(BlockFile::new({
         let f = OpenOptions::new()
         .read(true)
         .write(true)
         .create(true)
-        .open("target/fs.img").unwrap();
-        f.set_len(4096 * 4096).unwrap();
+        .truncate(true)
+        .open(&args.output)
+        .unwrap_or_else(|e| {
+            eprintln!("cannot open image {}: {}", args.output.display(), e);
+            exit(1);
+        });
+        f.set_len(args.total_blocks as u64 * BLOCK_SIZE as u64).unwrap();
         f
-    })));
-    let mut fs = FileSystem::create(block_file.clone(),4096,1);
-    fs.create_root_inode();
-    let fs = FileSystem::open(block_file.clone());
-    let mut root = FileSystem::root_inode(fs.clone());
-    root.create("test-file1");
-    root.create("test-file2");
-    root.create("test-file3");
-
-    let files = root.ls();
-    for f in files.iter() {
-        println!("{}", f);
+    }));
+    // 元数据区域：超级块 + 日志区域 + inode bitmap + inode块，至少还需要一个data bitmap块和一个数据块
+    let mut fs = FileSystem::create(block_file.clone(), args.total_blocks, args.inode_bitmap_blocks).unwrap_or_else(|_| {
+        eprintln!("image of {} blocks is too small for {} inode bitmap blocks", args.total_blocks, args.inode_bitmap_blocks);
+        exit(1);
+    });
+    fs.set_clock(host_clock);
+    fs.create_root_inode().unwrap();
+    let fs = FileSystem::open(block_file.clone()).unwrap();
+    fs.lock().set_clock(host_clock);
+    let root = FileSystem::root_inode(fs.clone());
+
+    for (dir, image_dir) in args.sources.iter() {
+        let target = make_dirs(&root, image_dir);
+        for (name, data) in collect_elf_files(dir) {
+            if name.len() > NAME_LIMIT {
+                eprintln!("skip {}: file name longer than {} bytes", name, NAME_LIMIT);
+                continue;
+            }
+            let inode = match target.create(&name) {
+                Ok(inode) => inode,
+                Err(FsError::Exists) => {
+                    eprintln!("skip {}: file already exists", name);
+                    continue;
+                }
+                Err(e) => {
+                    eprintln!("cannot create {}: {}", name, e);
+                    exit(1);
+                }
+            };
+            if inode.write_at(0, &data) != Ok(data.len()) {
+                eprintln!("cannot write {}: {}", name, FsError::NoSpace);
+                exit(1);
+            }
+            println!("packed {}/{} ({} bytes)", image_dir.trim_end_matches('/'), name, data.len());
+        }
     }
+    // 将缓存中的脏块全部写回镜像
+    fs.lock().sync();
+    println!("image {} created, {} blocks", args.output.display(), args.total_blocks);
 }
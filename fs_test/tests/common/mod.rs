@@ -0,0 +1,33 @@
+use fs::block_cache::BLOCK_SIZE;
+use fs::block_device::BlockDevice;
+use fs::fs::FileSystem;
+use fs::vfs::INode;
+use std::sync::{Arc, Mutex};
+
+// 内存块设备
+pub struct MemDevice(Mutex<Vec<u8>>);
+
+impl MemDevice {
+    pub fn new(blocks: usize) -> Arc<Self> {
+        Arc::new(Self(Mutex::new(vec![0u8; blocks * BLOCK_SIZE])))
+    }
+}
+
+impl BlockDevice for MemDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.0.lock().unwrap()[block_id * BLOCK_SIZE..(block_id + 1) * BLOCK_SIZE]);
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.0.lock().unwrap()[block_id * BLOCK_SIZE..(block_id + 1) * BLOCK_SIZE].copy_from_slice(buf);
+    }
+}
+
+// 在内存设备上创建文件系统，返回文件系统和根目录
+pub fn new_fs(total_blocks: u32) -> (Arc<spin::Mutex<FileSystem>>, INode) {
+    let device: Arc<dyn BlockDevice> = MemDevice::new(total_blocks as usize);
+    FileSystem::create(Arc::clone(&device), total_blocks, 1).unwrap().create_root_inode().unwrap();
+    let fs = FileSystem::open(device).unwrap();
+    let root = FileSystem::root_inode(Arc::clone(&fs));
+    (fs, root)
+}
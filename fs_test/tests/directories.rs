@@ -0,0 +1,134 @@
+mod common;
+
+use common::new_fs;
+use fs::block_cache::BLOCK_SIZE;
+use fs::dir::{encode_entry, name_hash, parse_entries_partial, DIR_SIZE, MAX_BUCKETS};
+use fs::error::FsError;
+use fs::fsck::fsck;
+
+#[test]
+fn directories_cannot_be_cleared_or_truncated() {
+    let (_fs, root) = new_fs(4096);
+    let dir = root.mkdir("dir").unwrap();
+    dir.create("file").unwrap();
+    assert_eq!(dir.clear(), Err(FsError::IsDir));
+    assert_eq!(dir.truncate(0), Err(FsError::IsDir));
+    assert!(dir.find("file").is_ok());
+}
+
+#[test]
+fn directories_cannot_be_written() {
+    let (fs, root) = new_fs(4096);
+    let dir = root.mkdir("dir").unwrap();
+    let size = dir.size();
+    assert_eq!(dir.write_at(0, b"garbage"), Err(FsError::IsDir));
+    assert_eq!(dir.size(), size);
+    assert_eq!(dir.ls().unwrap(), vec![".", ".."]);
+    assert_eq!(fsck(&fs, false), vec![]);
+}
+
+// 长文件名占用8个槽，一个桶只能放下16个目录项
+fn long_name(i: usize) -> String {
+    format!("{:0>200}", i)
+}
+
+#[test]
+fn hashed_directory_grows_past_journal_capacity() {
+    let (fs, root) = new_fs(8192);
+    let dir = root.mkdir("dir").unwrap();
+    let file = dir.create("file").unwrap();
+    // 硬链接不分配inode，每次添加目录项最多分裂一个桶
+    let count = 1500;
+    for i in 0..count {
+        dir.link(&long_name(i), &file).unwrap();
+    }
+    let buckets = dir.size() / BLOCK_SIZE as u32;
+    assert!(buckets > 64, "only {} buckets", buckets);
+    for i in 0..count {
+        assert_eq!(dir.find(&long_name(i)).unwrap().inode_id(), file.inode_id());
+    }
+    assert_eq!(dir.ls().unwrap().len(), count + 3);
+    assert_eq!(file.stat().nlink, count as u32 + 1);
+    assert_eq!(fsck(&fs, false), vec![]);
+}
+
+#[test]
+fn colliding_names_stop_bucket_splits() {
+    let (fs, root) = new_fs(4096);
+    let dir = root.mkdir("dir").unwrap();
+    let file = dir.create("file").unwrap();
+    // 散列值低10位相同的长文件名，桶数不超过MAX_BUCKETS时总是落在同一个桶中
+    let mask = MAX_BUCKETS - 1;
+    let hash = name_hash(&long_name(0)) & mask;
+    let names: Vec<String> = (0..)
+        .map(long_name)
+        .filter(|name| name_hash(name) & mask == hash)
+        .take(20)
+        .collect();
+    for name in names.iter() {
+        dir.link(name, &file).unwrap();
+    }
+    // 文件名所在的桶分裂后仍放不下，目录退化为线性目录，不会一直分裂到MAX_BUCKETS个桶
+    let blocks = dir.stat().blocks;
+    assert!(blocks <= 4, "{} blocks", blocks);
+    for name in names.iter() {
+        assert_eq!(dir.find(name).unwrap().inode_id(), file.inode_id());
+    }
+    assert_eq!(file.stat().nlink, names.len() as u32 + 1);
+    assert_eq!(fsck(&fs, false), vec![]);
+    for name in names.iter() {
+        dir.unlink(name).unwrap();
+    }
+    assert_eq!(dir.ls().unwrap(), vec![".", "..", "file"]);
+    assert_eq!(fsck(&fs, false), vec![]);
+}
+
+#[test]
+fn partial_parse_keeps_entries_across_blocks() {
+    // 线性目录中的长文件名目录项可以跨越块边界
+    let mut data = vec![0u8; 2 * BLOCK_SIZE];
+    let name = long_name(7);
+    let start = BLOCK_SIZE - DIR_SIZE as usize * 2;
+    let entry = encode_entry(&name, 3).unwrap();
+    data[start..start + entry.len()].copy_from_slice(&entry);
+    let (records, parsed) = parse_entries_partial(&data[..BLOCK_SIZE], true);
+    assert!(records.is_empty());
+    assert_eq!(parsed, start);
+    let (records, _) = parse_entries_partial(&data[parsed..], false);
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].name.as_deref(), Some(name.as_str()));
+    assert_eq!(records[0].slot, 0);
+}
+
+#[test]
+fn hashed_directory_shrinks_after_unlink() {
+    let (fs, root) = new_fs(8192);
+    let free_before = fs.lock().statfs().free_blocks;
+    let dir = root.mkdir("dir").unwrap();
+    let file = dir.create("file").unwrap();
+    let count = 600;
+    for i in 0..count {
+        dir.link(&long_name(i), &file).unwrap();
+    }
+    let grown = dir.stat().blocks;
+    assert!(grown > 32, "only {} blocks", grown);
+    // 删除大部分目录项后合并空闲的桶，剩下的目录项仍能找到
+    for i in 0..count - 20 {
+        dir.unlink(&long_name(i)).unwrap();
+    }
+    let shrunk = dir.stat().blocks;
+    assert!(shrunk < grown / 4, "{} blocks after unlink, {} before", shrunk, grown);
+    for i in count - 20..count {
+        assert!(dir.find(&long_name(i)).is_ok());
+    }
+    assert_eq!(fsck(&fs, false), vec![]);
+    // 全部删除后只剩一个桶
+    for i in count - 20..count {
+        dir.unlink(&long_name(i)).unwrap();
+    }
+    dir.unlink("file").unwrap();
+    assert_eq!(dir.stat().blocks, 1);
+    assert_eq!(fsck(&fs, false), vec![]);
+    root.unlink("dir").unwrap();
+    assert_eq!(fs.lock().statfs().free_blocks, free_before);
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use common::new_fs;
+use fs::block_cache::BLOCK_SIZE;
+use fs::fs::FileSystem;
+use fs::fsck::{fsck, Problem};
+use std::sync::Arc;
+
+// 在一个事务中直接修改文件系统，模拟损坏的镜像
+fn corrupt(fs: &Arc<spin::Mutex<FileSystem>>, f: impl FnOnce(&mut FileSystem)) {
+    let mut fs = fs.lock();
+    fs.begin();
+    f(&mut fs);
+    fs.commit();
+}
+
+// 修复后再次检查应没有问题
+fn assert_repaired(fs: &Arc<spin::Mutex<FileSystem>>) {
+    assert!(!fsck(fs, true).is_empty());
+    assert_eq!(fsck(fs, false), vec![]);
+}
+
+#[test]
+fn fsck_frees_leaked_inode() {
+    let (fs, _root) = new_fs(4096);
+    let free_inodes = fs.lock().statfs().free_inodes;
+    let mut leaked = 0;
+    // 分配inode但不添加目录项
+    corrupt(&fs, |fs| leaked = fs.alloc_inode().unwrap());
+    assert!(fsck(&fs, false).contains(&Problem::LeakedInode(leaked)));
+    assert_repaired(&fs);
+    assert_eq!(fs.lock().statfs().free_inodes, free_inodes);
+}
+
+#[test]
+fn fsck_restores_cleared_bitmap_bit() {
+    let (fs, root) = new_fs(4096);
+    let file = root.create("file").unwrap();
+    let data = vec![7u8; BLOCK_SIZE];
+    file.write_at(0, &data).unwrap();
+    let block_dev = Arc::clone(&fs.lock().block_dev);
+    let block = file.read_disk_inode(|disk_inode| disk_inode.get_block_id(0, block_dev));
+    // 清除文件数据块在data bitmap中的位，超级块中的空闲计数不变
+    corrupt(&fs, |fs| {
+        let seq = block - fs.get_data_block_id(0);
+        fs.data_bitmap.dealloc(seq, Arc::clone(&fs.block_dev));
+    });
+    let problems = fsck(&fs, false);
+    assert!(problems.contains(&Problem::UnallocatedBlock(block)));
+    assert_repaired(&fs);
+    // 修复后该块重新被标记为已分配，不会再分配给其他文件
+    let other = root.create("other").unwrap();
+    other.write_at(0, &data).unwrap();
+    let mut buf = vec![0u8; BLOCK_SIZE];
+    assert_eq!(file.read_at(0, &mut buf), BLOCK_SIZE);
+    assert_eq!(buf, data);
+    assert_eq!(fsck(&fs, false), vec![]);
+}
+
+#[test]
+fn fsck_fixes_wrong_link_count() {
+    let (fs, root) = new_fs(4096);
+    let file = root.create("file").unwrap();
+    root.link("link", &file).unwrap();
+    let inode = file.inode_id();
+    corrupt(&fs, |_| file.modify_disk_inode(|disk_inode| disk_inode.nlink = 5));
+    assert!(fsck(&fs, false).contains(&Problem::BadLinkCount { inode, nlink: 5, entries: 2 }));
+    assert_repaired(&fs);
+    assert_eq!(file.stat().nlink, 2);
+}
+
+#[test]
+fn fsck_removes_dangling_entry() {
+    let (fs, root) = new_fs(4096);
+    let dir = root.mkdir("dir").unwrap();
+    let file = dir.create("file").unwrap();
+    dir.create("kept").unwrap();
+    let inode = file.inode_id();
+    // 回收inode但保留指向它的目录项
+    corrupt(&fs, |fs| fs.dealloc_inode(inode));
+    let problems = fsck(&fs, false);
+    assert!(problems.contains(&Problem::DanglingEntry { dir: dir.inode_id(), name: String::from("file"), inode }));
+    assert_repaired(&fs);
+    assert_eq!(dir.ls().unwrap(), vec![".", "..", "kept"]);
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use common::new_fs;
+use fs::block_cache::BLOCK_SIZE;
+use fs::fsck::fsck;
+
+// 读取整个文件
+fn read_all(file: &fs::vfs::INode) -> Vec<u8> {
+    let mut data = vec![0xffu8; file.size() as usize];
+    assert_eq!(file.read_at(0, &mut data), data.len());
+    data
+}
+
+#[test]
+fn holes_read_as_zero() {
+    let (fs, root) = new_fs(4096);
+    let file = root.create("file").unwrap();
+    let offset = 10 * BLOCK_SIZE + 5;
+    file.write_at(offset as u32, b"x").unwrap();
+    assert_eq!(file.size(), offset as u32 + 1);
+    // 只有最后一个块被分配
+    assert_eq!(file.stat().blocks, 1);
+    let data = read_all(&file);
+    assert!(data[..offset].iter().all(|byte| *byte == 0));
+    assert_eq!(data[offset], b'x');
+    // 从空洞中间开始读
+    let mut buf = vec![0xffu8; 100];
+    assert_eq!(file.read_at(3 * BLOCK_SIZE as u32 + 7, &mut buf), 100);
+    assert!(buf.iter().all(|byte| *byte == 0));
+    // 读取位置超过文件末尾
+    assert_eq!(file.read_at(offset as u32 + 1, &mut buf), 0);
+    assert_eq!(fsck(&fs, false), vec![]);
+}
+
+#[test]
+fn first_write_into_hole_allocates_block() {
+    let (fs, root) = new_fs(4096);
+    let file = root.create("file").unwrap();
+    // 二级索引范围内的块，需要二级索引块和一个一级索引块
+    let last = 2000 * BLOCK_SIZE;
+    file.write_at(last as u32, b"end").unwrap();
+    assert_eq!(file.stat().blocks, 3);
+    // 写入直接索引范围内的空洞，只分配一个数据块，块内其余部分仍读出为0
+    let offset = 5 * BLOCK_SIZE + 100;
+    file.write_at(offset as u32, b"middle").unwrap();
+    assert_eq!(file.stat().blocks, 4);
+    // 写入一级索引范围内的空洞，同时分配一级索引块
+    let indirect = 100 * BLOCK_SIZE + BLOCK_SIZE - 2;
+    file.write_at(indirect as u32, b"span").unwrap();
+    assert_eq!(file.stat().blocks, 4 + 2 + 1);
+    assert_eq!(file.size(), last as u32 + 3);
+    let data = read_all(&file);
+    let mut expected = vec![0u8; last + 3];
+    expected[offset..offset + 6].copy_from_slice(b"middle");
+    expected[indirect..indirect + 4].copy_from_slice(b"span");
+    expected[last..].copy_from_slice(b"end");
+    assert!(data == expected);
+    assert_eq!(fsck(&fs, false), vec![]);
+}
+
+#[test]
+fn truncate_extends_with_hole() {
+    let (fs, root) = new_fs(4096);
+    let free_blocks = fs.lock().statfs().free_blocks;
+    let file = root.create("file").unwrap();
+    file.write_at(0, b"head").unwrap();
+    file.truncate(50 * BLOCK_SIZE as u32).unwrap();
+    assert_eq!(file.size(), 50 * BLOCK_SIZE as u32);
+    assert_eq!(file.stat().blocks, 1);
+    let data = read_all(&file);
+    assert_eq!(&data[..4], b"head");
+    assert!(data[4..].iter().all(|byte| *byte == 0));
+    assert_eq!(fsck(&fs, false), vec![]);
+    // 缩小后重新扩大，被截掉的部分读出为0
+    file.truncate(2).unwrap();
+    file.truncate(8).unwrap();
+    assert_eq!(read_all(&file), b"he\0\0\0\0\0\0");
+    file.truncate(0).unwrap();
+    assert_eq!(file.stat().blocks, 0);
+    assert_eq!(fs.lock().statfs().free_blocks, free_blocks);
+    assert_eq!(fsck(&fs, false), vec![]);
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use common::{new_fs, MemDevice};
+use fs::block_cache::BLOCK_SIZE;
+use fs::block_device::BlockDevice;
+use fs::fsck::fsck;
+use fs::inode::{DiskINode, INodeType};
+use fs::vfs::INode;
+use std::sync::Arc;
+
+// 直接索引、一级索引和二级索引中每个一级索引块的数据块数量
+const DIRECT_BLOCKS: u32 = 12;
+const INDEX_PER_BLOCK: u32 = BLOCK_SIZE as u32 / 4;
+// 第一个由二级索引记录的数据块序号
+const INDIRECT2_START: u32 = DIRECT_BLOCKS + INDEX_PER_BLOCK;
+
+// 每个索引层级边界两侧的文件块数，按从小到大排列，包括5MiB文件和跨越多个一级索引块的文件
+const BOUNDARY_BLOCKS: [u32; 9] = [
+    DIRECT_BLOCKS,
+    DIRECT_BLOCKS + 1,
+    INDIRECT2_START,
+    INDIRECT2_START + 1,
+    (5 << 20) / BLOCK_SIZE as u32,
+    (5 << 20) / BLOCK_SIZE as u32 + 1,
+    INDIRECT2_START + INDEX_PER_BLOCK,
+    INDIRECT2_START + INDEX_PER_BLOCK + 1,
+    INDIRECT2_START + 2 * INDEX_PER_BLOCK + 1,
+];
+
+// 测试数据，每个块的内容都不同，块放错位置时能被发现
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i / BLOCK_SIZE * 31 + i % 251) as u8).collect()
+}
+
+fn read_all(inode: &INode) -> Vec<u8> {
+    let mut data = vec![0u8; inode.size() as usize];
+    assert_eq!(inode.read_at(0, &mut data), data.len());
+    data
+}
+
+#[test]
+fn index_blocks_at_boundaries() {
+    let index_blocks = |blocks: u32| DiskINode::index_blocks_for_size(blocks * BLOCK_SIZE as u32);
+    assert_eq!(index_blocks(DIRECT_BLOCKS), 0);
+    assert_eq!(index_blocks(DIRECT_BLOCKS + 1), 1);
+    assert_eq!(index_blocks(INDIRECT2_START), 1);
+    // 一级索引块、二级索引块和二级索引中的第一个一级索引块
+    assert_eq!(index_blocks(INDIRECT2_START + 1), 3);
+    assert_eq!(index_blocks(INDIRECT2_START + INDEX_PER_BLOCK), 3);
+    assert_eq!(index_blocks(INDIRECT2_START + INDEX_PER_BLOCK + 1), 4);
+    assert_eq!(DiskINode::data_blocks_for_size(u32::MAX), 1 << 20);
+}
+
+#[test]
+fn increse_size_places_blocks_across_levels() {
+    let device: Arc<dyn BlockDevice> = MemDevice::new(8192);
+    // DiskINode是C布局的普通数据，全0是合法的空文件
+    let mut disk_inode: DiskINode = unsafe { std::mem::zeroed() };
+    disk_inode.init(INodeType::File, 0);
+    // 数据块从100开始编号，索引块从6000开始编号
+    let mut next_data = 100;
+    let mut next_index = 6000;
+    let mut expected: Vec<u32> = Vec::new();
+    for blocks in BOUNDARY_BLOCKS {
+        let new_size = blocks * BLOCK_SIZE as u32;
+        let index_count = DiskINode::index_blocks_for_size(new_size) - DiskINode::index_blocks_for_size(disk_inode.size);
+        let new_blocks: Vec<u32> = (next_data..next_data + blocks - disk_inode.data_blocks()).collect();
+        let index_blocks: Vec<u32> = (next_index..next_index + index_count).collect();
+        next_data += new_blocks.len() as u32;
+        next_index += index_count;
+        expected.extend_from_slice(&new_blocks);
+        disk_inode.increse_size(new_size, new_blocks, index_blocks, Arc::clone(&device));
+        for (seq, block_id) in expected.iter().enumerate() {
+            assert_eq!(disk_inode.get_block_id(seq as u32, Arc::clone(&device)), *block_id, "block {} of {}", seq, blocks);
+        }
+    }
+    // 缩小到0时返回所有数据块和索引块
+    let mut freed = disk_inode.decrease_size(0, Arc::clone(&device));
+    freed.sort_unstable();
+    let mut all: Vec<u32> = (100..next_data).chain(6000..next_index).collect();
+    all.sort_unstable();
+    assert_eq!(freed, all);
+    assert_eq!((disk_inode.indirect1, disk_inode.indirect2), (0, 0));
+}
+
+#[test]
+fn write_and_read_at_boundaries() {
+    let (fs, root) = new_fs(8192);
+    for blocks in BOUNDARY_BLOCKS {
+        // 块对齐的大小和最后一个块只写一个字节的大小
+        for len in [blocks as usize * BLOCK_SIZE, (blocks as usize - 1) * BLOCK_SIZE + 1] {
+            let file = root.create("boundary").unwrap();
+            let data = pattern(len);
+            assert_eq!(file.write_at(0, &data).unwrap(), len);
+            assert_eq!(read_all(&file), data);
+            let stat = file.stat();
+            assert_eq!(stat.blocks, DiskINode::data_blocks_for_size(len as u32) + DiskINode::index_blocks_for_size(len as u32));
+            assert_eq!(fsck(&fs, false), vec![]);
+            root.unlink("boundary").unwrap();
+        }
+    }
+    assert_eq!(fsck(&fs, false), vec![]);
+}
+
+#[test]
+fn append_across_boundaries() {
+    let (fs, root) = new_fs(8192);
+    let file = root.create("append").unwrap();
+    let last = BOUNDARY_BLOCKS[BOUNDARY_BLOCKS.len() - 1] as usize * BLOCK_SIZE;
+    let data = pattern(last);
+    // 每次追加的长度不是块大小的整数倍，追加的范围会跨越每个边界
+    let mut offset = 0;
+    for chunk in data.chunks(3 * BLOCK_SIZE + 123) {
+        assert_eq!(file.write_at(offset as u32, chunk).unwrap(), chunk.len());
+        offset += chunk.len();
+    }
+    assert_eq!(read_all(&file), data);
+    assert_eq!(fsck(&fs, false), vec![]);
+    // 从大到小截断到每个边界，剩余的内容不变
+    for blocks in BOUNDARY_BLOCKS.iter().rev() {
+        let len = *blocks as usize * BLOCK_SIZE;
+        file.truncate(len as u32).unwrap();
+        assert_eq!(read_all(&file), &data[..len]);
+        assert_eq!(fsck(&fs, false), vec![]);
+    }
+    let free_before = fs.lock().statfs().free_blocks;
+    let blocks = file.stat().blocks;
+    root.unlink("append").unwrap();
+    assert_eq!(fs.lock().statfs().free_blocks, free_before + blocks);
+}
+
+#[test]
+fn unlink_file_with_more_index_blocks_than_journal() {
+    let (fs, root) = new_fs(8192);
+    let free_before = fs.lock().statfs().free_blocks;
+    let file = root.create("sparse").unwrap();
+    // 在每个一级索引块的范围内写一个字节，回收时修改的索引块数量超过日志容量
+    for i in 0..70 {
+        let offset = (INDIRECT2_START + i * INDEX_PER_BLOCK) as usize * BLOCK_SIZE;
+        assert_eq!(file.write_at(offset as u32, &[1]).unwrap(), 1);
+    }
+    // 数据块、一级索引块和二级索引块
+    assert_eq!(file.stat().blocks, 70 + 70 + 1);
+    root.unlink("sparse").unwrap();
+    assert_eq!(fsck(&fs, false), vec![]);
+    assert_eq!(fs.lock().statfs().free_blocks, free_before);
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use common::new_fs;
+use fs::block_cache::BLOCK_SIZE;
+use fs::error::FsError;
+use fs::fsck::fsck;
+
+#[test]
+fn link_count_follows_link_and_unlink() {
+    let (fs, root) = new_fs(4096);
+    let free = fs.lock().statfs();
+    let dir = root.mkdir("dir").unwrap();
+    let file = root.create("file").unwrap();
+    let data = vec![3u8; 2 * BLOCK_SIZE];
+    file.write_at(0, &data).unwrap();
+    assert_eq!(file.stat().nlink, 1);
+    root.link("link", &file).unwrap();
+    dir.link("link", &file).unwrap();
+    assert_eq!(file.stat().nlink, 3);
+    assert_eq!(fsck(&fs, false), vec![]);
+    // 删除原来的名字后，数据仍可以通过其他链接访问
+    root.unlink("file").unwrap();
+    assert_eq!(file.stat().nlink, 2);
+    let linked = dir.find("link").unwrap();
+    assert_eq!(linked.inode_id(), file.inode_id());
+    let mut buf = vec![0u8; data.len()];
+    assert_eq!(linked.read_at(0, &mut buf), data.len());
+    assert_eq!(buf, data);
+    assert_eq!(fsck(&fs, false), vec![]);
+    root.unlink("link").unwrap();
+    assert_eq!(file.stat().nlink, 1);
+    assert_eq!(fsck(&fs, false), vec![]);
+    // 最后一个链接删除后回收inode和数据块
+    dir.unlink("link").unwrap();
+    root.unlink("dir").unwrap();
+    assert_eq!(fs.lock().statfs(), free);
+    assert_eq!(fsck(&fs, false), vec![]);
+}
+
+#[test]
+fn link_rejects_directories_and_existing_names() {
+    let (fs, root) = new_fs(4096);
+    let dir = root.mkdir("dir").unwrap();
+    let file = root.create("file").unwrap();
+    assert_eq!(root.link("dir_link", &dir), Err(FsError::IsDir));
+    assert_eq!(root.link("dir", &file), Err(FsError::Exists));
+    assert_eq!(root.link("..", &file), Err(FsError::InvalidName));
+    assert_eq!(file.link("link", &file), Err(FsError::NotDir));
+    assert_eq!(file.stat().nlink, 1);
+    assert_eq!(fsck(&fs, false), vec![]);
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use common::new_fs;
+use fs::dir::{slots_for_name, NAME_LIMIT, SHORT_NAME_LIMIT};
+use fs::error::FsError;
+use fs::fsck::fsck;
+
+#[test]
+fn names_up_to_limit() {
+    let (fs, root) = new_fs(4096);
+    let longest = "a".repeat(NAME_LIMIT);
+    let file = root.create(&longest).unwrap();
+    file.write_at(0, b"long").unwrap();
+    assert_eq!(root.find(&longest).unwrap().inode_id(), file.inode_id());
+    assert_eq!(root.ls().unwrap(), vec![".", "..", longest.as_str()]);
+    // 超过255字节的文件名
+    let too_long = "a".repeat(NAME_LIMIT + 1);
+    assert_eq!(root.create(&too_long).err(), Some(FsError::NameTooLong));
+    assert_eq!(root.find(&too_long).err(), Some(FsError::NotFound));
+    assert_eq!(fsck(&fs, false), vec![]);
+    root.unlink(&longest).unwrap();
+    assert_eq!(root.ls().unwrap(), vec![".", ".."]);
+    assert_eq!(fsck(&fs, false), vec![]);
+}
+
+#[test]
+fn names_around_short_name_limit() {
+    let (fs, root) = new_fs(4096);
+    // 短文件名和长文件名边界附近的长度，以及只有前缀相同的长文件名
+    let lengths = [1, SHORT_NAME_LIMIT - 1, SHORT_NAME_LIMIT, SHORT_NAME_LIMIT + 1, SHORT_NAME_LIMIT + 2, 64, NAME_LIMIT - 1, NAME_LIMIT];
+    assert_eq!(slots_for_name(SHORT_NAME_LIMIT), 1);
+    assert_eq!(slots_for_name(SHORT_NAME_LIMIT + 1), 2);
+    assert_eq!(slots_for_name(NAME_LIMIT), 9);
+    let mut names = Vec::new();
+    for len in lengths {
+        names.push("b".repeat(len));
+        names.push(format!("{}c", "b".repeat(len - 1)));
+    }
+    for name in names.iter() {
+        root.create(name).unwrap().write_at(0, name.as_bytes()).unwrap();
+    }
+    for name in names.iter() {
+        let file = root.find(name).unwrap();
+        let mut buf = vec![0u8; name.len()];
+        assert_eq!(file.read_at(0, &mut buf), name.len());
+        assert_eq!(buf, name.as_bytes());
+    }
+    let mut listed = root.ls().unwrap();
+    listed.sort();
+    let mut expected: Vec<String> = vec![String::from("."), String::from("..")];
+    expected.extend(names.iter().cloned());
+    expected.sort();
+    assert_eq!(listed, expected);
+    assert_eq!(fsck(&fs, false), vec![]);
+}
+
+#[test]
+fn long_names_in_linked_and_renamed_entries() {
+    let (fs, root) = new_fs(4096);
+    let dir = root.mkdir(&"d".repeat(NAME_LIMIT)).unwrap();
+    let file = dir.create(&"f".repeat(200)).unwrap();
+    dir.link(&"l".repeat(NAME_LIMIT), &file).unwrap();
+    dir.rename(&"f".repeat(200), &root, &"r".repeat(NAME_LIMIT)).unwrap();
+    assert_eq!(root.find_path(&format!("{}/{}", "d".repeat(NAME_LIMIT), "l".repeat(NAME_LIMIT))).unwrap().inode_id(), file.inode_id());
+    assert_eq!(root.find(&"r".repeat(NAME_LIMIT)).unwrap().inode_id(), file.inode_id());
+    assert_eq!(file.stat().nlink, 2);
+    assert_eq!(fsck(&fs, false), vec![]);
+}
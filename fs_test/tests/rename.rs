@@ -0,0 +1,81 @@
+mod common;
+
+use common::new_fs;
+use fs::block_cache::BLOCK_SIZE;
+use fs::error::FsError;
+use fs::fsck::fsck;
+
+#[test]
+fn rename_replaces_existing_target() {
+    let (fs, root) = new_fs(4096);
+    let free = fs.lock().statfs();
+    let dir = root.mkdir("dir").unwrap();
+    let target = dir.create("target").unwrap();
+    target.write_at(0, &vec![1u8; 3 * BLOCK_SIZE]).unwrap();
+    let tmp = root.create("tmp").unwrap();
+    tmp.write_at(0, b"new content").unwrap();
+    root.rename("tmp", &dir, "target").unwrap();
+    assert_eq!(root.find("tmp").err(), Some(FsError::NotFound));
+    let replaced = dir.find("target").unwrap();
+    assert_eq!(replaced.inode_id(), tmp.inode_id());
+    let mut buf = [0u8; 11];
+    assert_eq!(replaced.read_at(0, &mut buf), 11);
+    assert_eq!(&buf, b"new content");
+    assert_eq!(replaced.stat().nlink, 1);
+    assert_eq!(fsck(&fs, false), vec![]);
+    // 被替换的文件还有其他链接时只减少链接数
+    let other = dir.create("other").unwrap();
+    dir.link("other_link", &other).unwrap();
+    dir.rename("target", &dir, "other").unwrap();
+    assert_eq!(other.stat().nlink, 1);
+    assert_eq!(dir.find("other_link").unwrap().inode_id(), other.inode_id());
+    assert_eq!(fsck(&fs, false), vec![]);
+    // 被替换文件的inode和数据块都已回收
+    dir.unlink("other").unwrap();
+    dir.unlink("other_link").unwrap();
+    root.unlink("dir").unwrap();
+    assert_eq!(fs.lock().statfs(), free);
+}
+
+#[test]
+fn rename_checks_target_type() {
+    let (fs, root) = new_fs(4096);
+    let dir = root.mkdir("dir").unwrap();
+    root.mkdir("empty").unwrap();
+    let full = root.mkdir("full").unwrap();
+    full.create("file").unwrap();
+    let file = root.create("file").unwrap();
+    assert_eq!(root.rename("file", &root, "dir"), Err(FsError::IsDir));
+    assert_eq!(root.rename("dir", &root, "file"), Err(FsError::NotDir));
+    assert_eq!(root.rename("dir", &root, "full"), Err(FsError::NotEmpty));
+    // 目录可以替换空目录
+    root.rename("dir", &root, "empty").unwrap();
+    assert_eq!(root.find("empty").unwrap().inode_id(), dir.inode_id());
+    assert_eq!(root.find("dir").err(), Some(FsError::NotFound));
+    // 新旧名字指向同一个inode时不做任何修改
+    root.link("same", &file).unwrap();
+    root.rename("file", &root, "same").unwrap();
+    assert_eq!(root.find("file").unwrap().inode_id(), file.inode_id());
+    assert_eq!(file.stat().nlink, 2);
+    assert_eq!(fsck(&fs, false), vec![]);
+}
+
+#[test]
+fn rename_into_own_subtree() {
+    let (fs, root) = new_fs(4096);
+    let a = root.mkdir("a").unwrap();
+    let b = a.mkdir("b").unwrap();
+    let c = b.mkdir("c").unwrap();
+    assert_eq!(root.rename("a", &a, "a"), Err(FsError::InvalidArgument));
+    assert_eq!(root.rename("a", &c, "a"), Err(FsError::InvalidArgument));
+    assert_eq!(a.rename("b", &c, "b"), Err(FsError::InvalidArgument));
+    assert_eq!(root.find_path("a/b/c").unwrap().inode_id(), c.inode_id());
+    // 移动到兄弟目录和祖先目录可以完成，子目录的".."指向新的父目录
+    let d = root.mkdir("d").unwrap();
+    a.rename("b", &d, "b").unwrap();
+    assert_eq!(root.find_path("d/b/c").unwrap().inode_id(), c.inode_id());
+    assert_eq!(c.find_path("../..").unwrap().inode_id(), d.inode_id());
+    b.rename("c", &root, "c").unwrap();
+    assert_eq!(c.find("..").unwrap().inode_id(), root.inode_id());
+    assert_eq!(fsck(&fs, false), vec![]);
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use common::new_fs;
+use fs::error::FsError;
+use fs::fsck::fsck;
+use fs::vfs::{FOLLOW_LIMIT, SYMLINK_TARGET_LIMIT};
+
+// 创建 link1 -> link2 -> ... -> linkN -> target 的符号链接链
+fn link_chain(root: &fs::vfs::INode, prefix: &str, links: u32, target: &str) {
+    for i in 1..=links {
+        let next = if i == links { String::from(target) } else { format!("{}{}", prefix, i + 1) };
+        root.symlink(&format!("{}{}", prefix, i), &next).unwrap();
+    }
+}
+
+#[test]
+fn symlink_chain_up_to_follow_limit() {
+    let (fs, root) = new_fs(4096);
+    let dir = root.mkdir("dir").unwrap();
+    let file = dir.create("file").unwrap();
+    // 正好跟随FOLLOW_LIMIT次可以解析
+    link_chain(&root, "ok", FOLLOW_LIMIT, "dir/file");
+    assert_eq!(root.find_path("ok1").unwrap().inode_id(), file.inode_id());
+    // 多一次则返回TooManyLinks
+    link_chain(&root, "long", FOLLOW_LIMIT + 1, "dir/file");
+    assert_eq!(root.find_path("long2").unwrap().inode_id(), file.inode_id());
+    assert_eq!(root.find_path("long1").err(), Some(FsError::TooManyLinks));
+    // 路径中间的符号链接同样计数
+    link_chain(&root, "mid", FOLLOW_LIMIT, "dir");
+    assert_eq!(root.find_path("mid1/file").unwrap().inode_id(), file.inode_id());
+    dir.symlink("up", "/mid1").unwrap();
+    assert_eq!(root.find_path("mid1/up/file").err(), Some(FsError::TooManyLinks));
+    assert_eq!(fsck(&fs, false), vec![]);
+}
+
+#[test]
+fn symlink_loops_stop_at_follow_limit() {
+    let (fs, root) = new_fs(4096);
+    root.symlink("self", "self").unwrap();
+    root.symlink("a", "b").unwrap();
+    root.symlink("b", "/a").unwrap();
+    let dir = root.mkdir("dir").unwrap();
+    dir.symlink("parent", "..").unwrap();
+    dir.symlink("loop", "../dir/loop").unwrap();
+    assert_eq!(root.find_path("self").err(), Some(FsError::TooManyLinks));
+    assert_eq!(root.find_path("a").err(), Some(FsError::TooManyLinks));
+    assert_eq!(root.find_path("/b/x").err(), Some(FsError::TooManyLinks));
+    assert_eq!(root.find_path("dir/loop").err(), Some(FsError::TooManyLinks));
+    // 指向祖先目录的符号链接不是循环，只要跟随次数不超过限制就能解析
+    assert_eq!(root.find_path("dir/parent/dir/parent/dir").unwrap().inode_id(), dir.inode_id());
+    // 符号链接本身仍可以读取和删除
+    assert_eq!(root.find("a").unwrap().readlink().unwrap(), "b");
+    root.unlink("self").unwrap();
+    assert_eq!(fsck(&fs, false), vec![]);
+}
+
+#[test]
+fn symlink_targets() {
+    let (fs, root) = new_fs(4096);
+    let file = root.create("file").unwrap();
+    // 目标可以不存在
+    root.symlink("dangling", "missing").unwrap();
+    assert_eq!(root.find_path("dangling").err(), Some(FsError::NotFound));
+    assert_eq!(root.symlink("empty", "").err(), Some(FsError::InvalidName));
+    let longest = "/".repeat(SYMLINK_TARGET_LIMIT - 4) + "file";
+    root.symlink("longest", &longest).unwrap();
+    assert_eq!(root.find("longest").unwrap().readlink().unwrap(), longest);
+    assert_eq!(root.find_path("longest").unwrap().inode_id(), file.inode_id());
+    assert_eq!(root.symlink("too_long", &"/".repeat(SYMLINK_TARGET_LIMIT + 1)).err(), Some(FsError::NameTooLong));
+    assert_eq!(file.readlink(), Err(FsError::NotLink));
+    assert_eq!(fsck(&fs, false), vec![]);
+}
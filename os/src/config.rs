@@ -14,6 +14,13 @@ pub const PAGE_OFFSET_MASK: usize = 0xfff;
 
 pub const MEMORY_END: usize = 0x88000000;
 
+// QEMU virt机器的virtio-mmio块设备地址
+pub const VIRTIO0: usize = 0x10001000;
+// 需要在内核地址空间直接映射的MMIO区域 (起始地址, 长度)
+pub const MMIO: &[(usize, usize)] = &[(VIRTIO0, 0x1000)];
+// 文件系统块缓存容量，单位为块
+pub const BLOCK_CACHE_CAPACITY: usize = 64;
+
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
 
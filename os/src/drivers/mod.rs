@@ -0,0 +1,11 @@
+pub mod virtio_blk;
+
+use fs::block_device::BlockDevice;
+use virtio_blk::VirtIOBlock;
+use alloc::sync::Arc;
+use lazy_static::lazy_static;
+
+lazy_static! {
+    // 根文件系统所在的块设备
+    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = Arc::new(VirtIOBlock::new());
+}
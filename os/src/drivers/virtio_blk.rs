@@ -0,0 +1,92 @@
+use virtio_drivers::{VirtIOBlk, VirtIOHeader};
+use fs::block_device::BlockDevice;
+use fs::block_cache::BLOCK_SIZE;
+use crate::sync::UPSafeCell;
+use crate::mem::address::{PhysAddr, PhysPageNumber, VirtAddr};
+use crate::mem::frame_allocator::{alloc_frame, FrameTracker};
+use crate::mem::kernel::KERNEL_SPACE;
+use crate::config::VIRTIO0;
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+
+// virtio块设备的扇区大小
+const SECTOR_SIZE: usize = 512;
+// 一个文件系统块包含的扇区数量
+const SECTORS_PER_BLOCK: usize = BLOCK_SIZE / SECTOR_SIZE;
+
+// QEMU virt机器上的virtio-mmio块设备
+pub struct VirtIOBlock(UPSafeCell<VirtIOBlk<'static>>);
+
+lazy_static! {
+    // virtio队列使用的DMA物理页，持有FrameTracker防止被回收
+    static ref QUEUE_FRAMES: UPSafeCell<Vec<FrameTracker>> = unsafe {UPSafeCell::new(Vec::new())};
+}
+
+impl VirtIOBlock {
+    pub fn new() -> Self {
+        unsafe {
+            let header = &mut *(VIRTIO0 as *mut VirtIOHeader);
+            return Self(UPSafeCell::new(VirtIOBlk::new(header).expect("virtio block device init failed")));
+        }
+    }
+}
+
+// 文件系统块大小为4KiB，virtio设备按512字节扇区读写，一个块拆分成多个扇区
+impl BlockDevice for VirtIOBlock {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        let mut blk = self.0.exclusive_borrow();
+        for (i, sector) in buf.chunks_mut(SECTOR_SIZE).enumerate() {
+            blk.read_block(block_id * SECTORS_PER_BLOCK + i, sector)
+            .expect("virtio block device read failed");
+        }
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let mut blk = self.0.exclusive_borrow();
+        for (i, sector) in buf.chunks(SECTOR_SIZE).enumerate() {
+            blk.write_block(block_id * SECTORS_PER_BLOCK + i, sector)
+            .expect("virtio block device write failed");
+        }
+    }
+}
+
+// 为virtio队列分配连续的物理页，返回起始物理地址
+#[no_mangle]
+pub extern "C" fn virtio_dma_alloc(pages: usize) -> usize {
+    let mut frames = QUEUE_FRAMES.exclusive_borrow();
+    let mut base_ppn = PhysPageNumber(0);
+    for i in 0..pages {
+        let frame = alloc_frame().unwrap();
+        if i == 0 {
+            base_ppn = frame.ppn;
+        }
+        // 栈式分配器连续分配的物理页号是连续的
+        assert_eq!(frame.ppn.0, base_ppn.0 + i, "dma frames not continuous");
+        frames.push(frame);
+    }
+    return base_ppn.get_base_address();
+}
+
+// 回收DMA物理页，丢弃FrameTracker即可释放物理页
+#[no_mangle]
+pub extern "C" fn virtio_dma_dealloc(paddr: usize, pages: usize) -> i32 {
+    let base_ppn = PhysAddr::new(paddr).floor().0;
+    QUEUE_FRAMES.exclusive_borrow().retain(|frame| {
+        frame.ppn.0 < base_ppn || frame.ppn.0 >= base_ppn + pages
+    });
+    return 0;
+}
+
+// 内核地址空间是直接映射，物理地址与虚拟地址相同
+#[no_mangle]
+pub extern "C" fn virtio_phys_to_virt(paddr: usize) -> usize {
+    return paddr;
+}
+
+#[no_mangle]
+pub extern "C" fn virtio_virt_to_phys(vaddr: usize) -> usize {
+    return KERNEL_SPACE.exclusive_borrow()
+    .page_table
+    .translate_virt_addr(VirtAddr::new(vaddr))
+    .unwrap();
+}
@@ -0,0 +1,189 @@
+use super::File;
+use crate::mem::page_table::UserBuffer;
+use crate::sync::UPSafeCell;
+use fs::vfs::{INode, Stat};
+use fs::error::{FsError, FsResult};
+use fs::block_cache::BLOCK_SIZE;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use bitflags::bitflags;
+
+bitflags! {
+    // open系统调用flags，与user_lib中的定义保持一致
+    pub struct OpenFlags: u32 {
+        const READ = 0;
+        const WRITE = 1 << 0;
+        const RDWR = 1 << 1;
+        const CREATE = 1 << 9;
+        const TRUNC = 1 << 10;
+    }
+}
+
+impl OpenFlags {
+    // 返回 (可读, 可写)
+    pub fn read_write(&self) -> (bool, bool) {
+        if self.contains(Self::RDWR) {
+            return (true, true);
+        }else if self.contains(Self::WRITE) {
+            return (false, true);
+        }
+        return (true, false);
+    }
+}
+
+// 进程打开的磁盘文件，记录读写权限和当前读写位置
+pub struct OSInode {
+    readable: bool,
+    writable: bool,
+    inner: UPSafeCell<OSInodeInner>,
+}
+
+struct OSInodeInner {
+    offset: usize,     // 当前读写偏移
+    inode: Arc<INode>, // 文件系统inode
+}
+
+lazy_static! {
+    // 挂载的根目录inode，挂载文件系统之前为None
+    static ref ROOT_INODE: UPSafeCell<Option<Arc<INode>>> = unsafe {UPSafeCell::new(None)};
+}
+
+// 挂载文件系统的根目录
+pub fn mount_root(root: INode) {
+    *ROOT_INODE.exclusive_borrow() = Some(Arc::new(root));
+}
+
+// 获取根目录inode
+pub fn root_inode() -> Option<Arc<INode>> {
+    return ROOT_INODE.exclusive_borrow().as_ref().map(|root| {Arc::clone(root)});
+}
+
+impl OSInode {
+    pub fn new(readable: bool, writable: bool, inode: Arc<INode>) -> Self {
+        return Self {
+            readable,
+            writable,
+            inner: unsafe {UPSafeCell::new(OSInodeInner { offset: 0, inode })},
+        };
+    }
+
+    // 从当前偏移读取文件剩余的全部数据，每次读取一个块大小
+    pub fn read_all(&self) -> Vec<u8> {
+        let mut inner = self.inner.exclusive_borrow();
+        let mut data: Vec<u8> = Vec::new();
+        let mut buffer = [0u8; BLOCK_SIZE];
+        loop {
+            let len = inner.inode.read_at(inner.offset as u32, &mut buffer);
+            // 读到文件末尾
+            if len == 0 {
+                break;
+            }
+            data.extend_from_slice(&buffer[..len]);
+            inner.offset += len;
+        }
+        return data;
+    }
+}
+
+// 将路径拆分为父目录路径和文件名，如 "/bin/sh" 拆分为 ("/bin", "sh")
+fn split_path(path: &str) -> (&str, &str) {
+    let path = path.trim_end_matches('/');
+    return match path.rfind('/') {
+        Some(idx) => (&path[..idx], &path[idx + 1..]),
+        None => ("", path),
+    };
+}
+
+// 从根目录解析路径打开文件，根据flags创建或清空文件
+pub fn open_file(path: &str, flags: OpenFlags) -> FsResult<Arc<OSInode>> {
+    let root = root_inode().ok_or(FsError::NotFound)?;
+    let (readable, writable) = flags.read_write();
+    let inode = match root.find_path(path) {
+        Ok(inode) => {
+            // 目录不能以写方式打开，也不能被TRUNC清空，READ为0，不能只检查writable
+            if inode.is_dir() && (writable || flags.contains(OpenFlags::TRUNC)) {
+                return Err(FsError::IsDir);
+            }
+            // 文件已存在，TRUNC清空文件内容
+            if flags.contains(OpenFlags::TRUNC) {
+                inode.clear()?;
+            }
+            Arc::new(inode)
+        },
+        Err(FsError::NotFound) => {
+            // 文件不存在，只有CREATE才创建新文件
+            if !flags.contains(OpenFlags::CREATE) {
+                return Err(FsError::NotFound);
+            }
+            let (dir_path, name) = split_path(path);
+            root.find_path(dir_path)?.create(name)?
+        },
+        Err(err) => return Err(err),
+    };
+    return Ok(Arc::new(OSInode::new(readable, writable, inode)));
+}
+
+// 根据路径创建目录，父目录必须已经存在
+pub fn mkdir(path: &str) -> FsResult<()> {
+    let root = root_inode().ok_or(FsError::NotFound)?;
+    let (dir_path, name) = split_path(path);
+    root.find_path(dir_path)?.mkdir(name)?;
+    return Ok(());
+}
+
+// 将old_path移动到new_path，new_path已存在时替换原有的文件，两个路径的父目录必须已经存在
+pub fn rename(old_path: &str, new_path: &str) -> FsResult<()> {
+    let root = root_inode().ok_or(FsError::NotFound)?;
+    let (old_dir_path, old_name) = split_path(old_path);
+    let (new_dir_path, new_name) = split_path(new_path);
+    let old_dir = root.find_path(old_dir_path)?;
+    let new_dir = root.find_path(new_dir_path)?;
+    return old_dir.rename(old_name, &new_dir, new_name);
+}
+
+impl File for OSInode {
+    fn readable(&self) -> bool {
+        return self.readable;
+    }
+    fn writable(&self) -> bool {
+        return self.writable;
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        let mut inner = self.inner.exclusive_borrow();
+        let mut total = 0;
+        for slice in buf.buffers.iter_mut() {
+            let len = inner.inode.read_at(inner.offset as u32, slice);
+            inner.offset += len;
+            total += len;
+            // 读到文件末尾
+            if len < slice.len() {
+                break;
+            }
+        }
+        return total;
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        let mut inner = self.inner.exclusive_borrow();
+        let mut total = 0;
+        for slice in buf.buffers.iter() {
+            // 空间不足时只返回已经写入的字节数
+            let written = match inner.inode.write_at(inner.offset as u32, slice) {
+                Ok(written) => written,
+                Err(_) => break,
+            };
+            inner.offset += written;
+            total += written;
+            if written < slice.len() {
+                break;
+            }
+        }
+        return total;
+    }
+    fn sync(&self) {
+        self.inner.exclusive_borrow().inode.fsync();
+    }
+    fn stat(&self) -> Option<Stat> {
+        return Some(self.inner.exclusive_borrow().inode.stat());
+    }
+}
@@ -0,0 +1,77 @@
+pub mod stdio;
+pub mod inode;
+
+use crate::mem::page_table::UserBuffer;
+use crate::drivers::BLOCK_DEVICE;
+use crate::config::{BLOCK_CACHE_CAPACITY, TIME_FREQUENCY};
+use crate::timer::get_time;
+use fs::fs::FileSystem;
+use fs::block_cache::set_block_cache_capacity;
+use fs::vfs::Stat;
+use alloc::sync::Arc;
+use bitflags::bitflags;
+
+bitflags! {
+    // fstat返回的文件类型，与user_lib中的定义保持一致
+    pub struct StatMode: u32 {
+        const DIR = 0o040000;
+        const FILE = 0o100000;
+        const LINK = 0o120000;
+    }
+}
+
+// fstat系统调用写入用户空间的文件状态，与user_lib中的定义保持一致
+#[repr(C)]
+pub struct FileStat {
+    pub ino: u32,    // inode编号
+    pub mode: u32,   // 文件类型StatMode和rwx权限位
+    pub size: u32,   // 文件大小
+    pub blocks: u32, // 占用的数据块和索引块数量
+    pub uid: u32,    // 所有者用户id
+    pub gid: u32,    // 所有者组id
+    pub atime: u32,  // 最后访问时间，单位秒
+    pub mtime: u32,  // 文件内容最后修改时间
+    pub ctime: u32,  // inode最后修改时间
+    pub nlink: u32,  // 链接数
+}
+
+// 进程fd表中的文件对象，标准输入输出和磁盘文件都实现该trait
+pub trait File: Send + Sync {
+    fn readable(&self) -> bool;
+    fn writable(&self) -> bool;
+    // 从文件读取数据到用户缓冲区，返回读取的字节数
+    fn read(&self, buf: UserBuffer) -> usize;
+    // 将用户缓冲区的数据写入文件，返回写入的字节数
+    fn write(&self, buf: UserBuffer) -> usize;
+    // 将文件被修改的数据写回存储设备
+    fn sync(&self);
+    // 文件状态，不是磁盘文件时返回None
+    fn stat(&self) -> Option<Stat>;
+}
+
+// 文件系统时钟，内核没有实时时钟，使用启动以来的秒数
+fn fs_clock() -> u32 {
+    return (get_time() / TIME_FREQUENCY) as u32;
+}
+
+// 将所有文件系统被修改的缓存块写回块设备
+pub fn sync_all() {
+    if let Some(root) = inode::root_inode() {
+        root.fsync();
+    }
+}
+
+// 打开块设备上的文件系统，挂载根目录，块设备上没有有效的文件系统时不挂载
+pub fn init() {
+    set_block_cache_capacity(BLOCK_CACHE_CAPACITY);
+    match FileSystem::open(Arc::clone(&BLOCK_DEVICE)) {
+        Ok(fs) => {
+            fs.lock().set_clock(fs_clock);
+            inode::mount_root(FileSystem::root_inode(fs));
+            kernel_info!("file system mounted, root files: {:?}", inode::root_inode().unwrap().ls().unwrap_or_default());
+        },
+        Err(err) => {
+            kernel_info!("file system not mounted: {}", err);
+        },
+    }
+}
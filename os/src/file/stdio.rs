@@ -0,0 +1,66 @@
+use super::File;
+use crate::mem::page_table::UserBuffer;
+use crate::proc::suspend_current_and_run_next;
+use crate::sbi::console_get_char;
+use fs::vfs::Stat;
+
+// 标准输入，fd 0
+pub struct Stdin;
+// 标准输出，fd 1和2
+pub struct Stdout;
+
+impl File for Stdin {
+    fn readable(&self) -> bool {
+        return true;
+    }
+    fn writable(&self) -> bool {
+        return false;
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        assert_eq!(buf.len(), 1, "currently only allow 1 byte per read");
+        let mut c: usize;
+        loop {
+            c = console_get_char();
+            // 没有输入，让出处理器
+            if c == 0 {
+                suspend_current_and_run_next();
+                continue;
+            }else {
+                break;
+            }
+        }
+        let ch = c as u8;
+        unsafe {buf.buffers[0].as_mut_ptr().write_volatile(ch);}
+        return 1;
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        panic!("cannot write to stdin");
+    }
+    fn sync(&self) {}
+    fn stat(&self) -> Option<Stat> {
+        return None;
+    }
+}
+
+impl File for Stdout {
+    fn readable(&self) -> bool {
+        return false;
+    }
+    fn writable(&self) -> bool {
+        return true;
+    }
+    fn read(&self, _buf: UserBuffer) -> usize {
+        panic!("cannot read from stdout");
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        for buffer in buf.buffers.iter() {
+            print!("{}", core::str::from_utf8(buffer).unwrap());
+        }
+        return buf.len();
+    }
+    // 标准输出直接打印，没有需要写回的数据
+    fn sync(&self) {}
+    fn stat(&self) -> Option<Stat> {
+        return None;
+    }
+}
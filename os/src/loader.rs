@@ -1,5 +1,9 @@
 use lazy_static::lazy_static;
 use alloc::vec::Vec;
+use crate::file::inode::{open_file, OpenFlags};
+
+// ELF文件开头的magic num
+const ELF_MAGIC: [u8; 4] = [0x7f, 0x45, 0x4c, 0x46];
 
 pub fn get_num_apps() -> usize {
     extern "C" {
@@ -52,6 +56,19 @@ pub fn get_app_data_by_name(name: &str) -> Option<&'static [u8]> {
     .map(|app_id| {load_app_data(app_id)});
 }
 
+// 根据路径获取app的ELF数据，优先从文件系统读取，找不到时使用link_app.S中内嵌的app
+pub fn get_app_data(path: &str) -> Option<Vec<u8>> {
+    if let Ok(inode) = open_file(path, OpenFlags::READ) {
+        let data = inode.read_all();
+        // 文件存在但不是ELF，不能被加载执行
+        if data.len() < ELF_MAGIC.len() || data[..ELF_MAGIC.len()] != ELF_MAGIC {
+            return None;
+        }
+        return Some(data);
+    }
+    return get_app_data_by_name(path).map(|data| {data.to_vec()});
+}
+
 pub fn list_apps() {
     for (app_id, app_name) in APP_NAMES.iter().enumerate() {
         println!("app_id: {}, name: {}", app_id, app_name);
@@ -18,6 +18,8 @@ mod config;
 mod timer;
 mod mem;
 mod proc;
+mod file;
+mod drivers;
 
 use core::arch::global_asm;
 // 让编译器将该汇编代码文件作为编入全局代码
@@ -37,6 +39,7 @@ pub fn rust_main() {
     // 清空bss段
     clear_bss();
     mem::init();
+    file::init();
     loader::list_apps();
     trap::enable_stimer();
     timer::set_next_time_trigger();
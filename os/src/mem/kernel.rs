@@ -1,6 +1,6 @@
 use super::memory_set::{MemorySet, MemoryArea, MapType, MapPermission};
 use super::address::{VirtAddr};
-use crate::config::MEMORY_END;
+use crate::config::{MEMORY_END, MMIO};
 use lazy_static::lazy_static;
 use crate::sync::UPSafeCell;
 use alloc::sync::Arc;
@@ -53,6 +53,14 @@ impl MemorySet {
                 VirtAddr::new(MEMORY_END),
                 MapType::Direct, MapPermission::R | MapPermission::W ), None);
         kernel_info!("physical memory mapped, mem range: [{:#x},{:#x})", ekernel as usize, MEMORY_END);
+        // 映射设备MMIO区域
+        for &(start, len) in MMIO {
+            memory_set.push(MemoryArea::new(
+                    VirtAddr::new(start),
+                    VirtAddr::new(start + len),
+                    MapType::Direct, MapPermission::R | MapPermission::W), None);
+            kernel_info!("MMIO mapped, mem range: [{:#x},{:#x})", start, start + len);
+        }
         return memory_set;
     }
 }
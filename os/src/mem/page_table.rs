@@ -218,4 +218,19 @@ where T: Sized{
         let ptr = addr as *mut T;
         return ptr.as_mut().unwrap();
     }
-}
\ No newline at end of file
+}
+
+// 用户空间缓冲区，由若干个已经转换到物理地址的切片组成
+pub struct UserBuffer {
+    pub buffers: Vec<&'static mut [u8]>,
+}
+
+impl UserBuffer {
+    pub fn new(buffers: Vec<&'static mut [u8]>) -> Self {
+        return Self { buffers };
+    }
+    // 缓冲区总长度
+    pub fn len(&self) -> usize {
+        return self.buffers.iter().map(|b| {b.len()}).sum();
+    }
+}
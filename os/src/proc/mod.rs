@@ -10,7 +10,7 @@ use crate::sync::UPSafeCell;
 use pcb::*;
 use context::*;
 use crate::trap::context::TrapContext;
-use crate::loader::get_app_data_by_name;
+use crate::loader::get_app_data;
 
 // 切换进程的汇编函数
 extern "C" {
@@ -36,7 +36,7 @@ lazy_static! {
 }
 
 lazy_static! {
-    pub static ref INIT_PROC: Arc<ProcessControlBlock> = Arc::new(ProcessControlBlock::new(get_app_data_by_name("init_proc").unwrap()));
+    pub static ref INIT_PROC: Arc<ProcessControlBlock> = Arc::new(ProcessControlBlock::new(&get_app_data("init_proc").unwrap()));
 }
 
 pub fn add_initproc() {
@@ -71,6 +71,8 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     }
     drop(init_proc_inner);
     inner.children.clear();
+    // 关闭进程打开的文件
+    inner.fd_table.clear();
     // 回收memory资源
     inner.memory_set.recycle_memory_set();
     // 回收PCB
@@ -15,6 +15,9 @@ use super::pid::alloc_pid;
 use super::stack::{kernel_stack_position};
 use crate::trap::trap_handler;
 use crate::mem::kernel::KERNEL_SPACE;
+use crate::file::File;
+use crate::file::stdio::{Stdin, Stdout};
+use alloc::vec;
 
 #[derive(Clone,Copy,PartialEq, Eq)]
 pub enum ProcessStatus {
@@ -43,6 +46,7 @@ pub struct InnerPCB {
     pub children: Vec<Arc<ProcessControlBlock>>, // 子进程PCB引用集合
     pub exit_code: i32, // 进程退出代码
     pub status: ProcessStatus,
+    pub fd_table: Vec<Option<Arc<dyn File>>>, // 文件描述符表，下标即fd
 }
 
 impl InnerPCB {
@@ -58,6 +62,14 @@ impl InnerPCB {
     pub fn get_status(&self) -> ProcessStatus {
         return self.status;
     }
+    // 分配一个空闲的fd，没有空闲位置则扩展fd表
+    pub fn alloc_fd(&mut self) -> usize {
+        if let Some(fd) = (0..self.fd_table.len()).find(|fd| {self.fd_table[*fd].is_none()}) {
+            return fd;
+        }
+        self.fd_table.push(None);
+        return self.fd_table.len() - 1;
+    }
 }
 
 impl ProcessControlBlock {
@@ -85,6 +97,12 @@ impl ProcessControlBlock {
             children: Vec::new(),
             exit_code: 0,
             status: ProcessStatus::Ready,
+            // 默认打开标准输入、标准输出和标准错误
+            fd_table: vec![
+                Some(Arc::new(Stdin)),
+                Some(Arc::new(Stdout)),
+                Some(Arc::new(Stdout)),
+            ],
         };
         let trap_ctx = inner.get_trap_context();
         // 创建trap context，sepc指向app_entry
@@ -118,6 +136,8 @@ impl ProcessControlBlock {
             children: Vec::new(),
             exit_code: 0,
             status: ProcessStatus::Ready,
+            // 子进程继承父进程打开的文件
+            fd_table: inner.fd_table.clone(),
         };
         let pcb = Arc::new(ProcessControlBlock{
             inner: unsafe{UPSafeCell::new(inner_pcb)},
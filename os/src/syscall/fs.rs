@@ -1,48 +1,192 @@
-use crate::mem::page_table::translated_byte_buffer;
-use crate::proc::{current_proc_satp, suspend_current_and_run_next};
-use crate::sbi::console_get_char;
+use crate::mem::page_table::{translated_byte_buffer, translate_string, translate_ptr, UserBuffer};
+use crate::proc::{current_process, current_proc_satp};
+use crate::file::inode::{open_file, mkdir, rename, root_inode, OpenFlags};
+use crate::file::{sync_all, FileStat, StatMode};
+use fs::fs::StatFs;
+use fs::inode::INodeType;
+use fs::error::FsError;
 
+// 文件系统错误对应的Linux错误码，返回值为错误码的相反数
+fn fs_errno(err: FsError) -> isize {
+    let errno = match err {
+        FsError::NotFound => 2,         // ENOENT
+        FsError::InvalidImage => 5,     // EIO
+        FsError::Exists => 17,          // EEXIST
+        FsError::NotDir => 20,          // ENOTDIR
+        FsError::IsDir => 21,           // EISDIR
+        FsError::InvalidName | FsError::InvalidArgument | FsError::NotLink | FsError::OutOfRange => 22, // EINVAL
+        FsError::NoSpace => 28,         // ENOSPC
+        FsError::NameTooLong => 36,     // ENAMETOOLONG
+        FsError::NotEmpty => 39,        // ENOTEMPTY
+        FsError::TooManyLinks => 40,    // ELOOP
+    };
+    return -errno;
+}
 
-const FD_STDOUT: usize = 1;
-const FD_STDIN: usize = 0;
-
+// 通过fd表写文件，标准输出也作为文件处理
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
-    match fd {
-        FD_STDOUT => {
-            let buffers = translated_byte_buffer(current_proc_satp(), buf, len);
-            for buffer in buffers {
-                print!("{}", core::str::from_utf8(buffer).unwrap());
-            }
-            len as isize
-        },
-        _ => {
-            panic!("Unsupported fd in sys_write!");
+    let proc = current_process().unwrap();
+    let inner = proc.exclusive_borrow_inner();
+    if fd >= inner.fd_table.len() {
+        return -1;
+    }
+    if let Some(file) = &inner.fd_table[fd] {
+        if !file.writable() {
+            return -1;
         }
+        let file = file.clone();
+        let satp = inner.user_space_satp();
+        // 写文件可能导致进程切换，先释放PCB的借用
+        drop(inner);
+        let buffers = translated_byte_buffer(satp, buf, len);
+        return file.write(UserBuffer::new(buffers)) as isize;
+    }else {
+        return -1;
     }
 }
 
+// 通过fd表读文件，标准输入也作为文件处理
 pub fn sys_read(fd: usize, buf: *mut u8, len: usize) -> isize {
-    match fd {
-        FD_STDIN=> {
-            assert_eq!(len, 1, "currently only allow 1 byte per read");
-            let mut c: usize;
-            loop {
-                c = console_get_char();
-                if c == 0 {
-                    suspend_current_and_run_next();
-                    continue;
-                }else {
-                    break;
-                }
-            }
-            let ch = c as u8;
-            let mut buffers = translated_byte_buffer(current_proc_satp(), buf, len);
-            unsafe {buffers[0].as_mut_ptr().write_volatile(ch);}
-            return len as isize;
-        },
-        _ => {
-            panic!("unsupported fd in sys_read");
+    let proc = current_process().unwrap();
+    let inner = proc.exclusive_borrow_inner();
+    if fd >= inner.fd_table.len() {
+        return -1;
+    }
+    if let Some(file) = &inner.fd_table[fd] {
+        if !file.readable() {
+            return -1;
         }
+        let file = file.clone();
+        let satp = inner.user_space_satp();
+        // 读标准输入时会让出处理器，先释放PCB的借用
+        drop(inner);
+        let buffers = translated_byte_buffer(satp, buf, len);
+        return file.read(UserBuffer::new(buffers)) as isize;
+    }else {
+        return -1;
+    }
+}
+
+// 打开文件，返回分配的fd
+pub fn sys_open(path: *const u8, flags: u32) -> isize {
+    let path_str = translate_string(current_proc_satp(), path);
+    let flags = match OpenFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return -1,
+    };
+    if let Ok(inode) = open_file(&path_str, flags) {
+        let proc = current_process().unwrap();
+        let mut inner = proc.exclusive_borrow_inner();
+        let fd = inner.alloc_fd();
+        inner.fd_table[fd] = Some(inode);
+        return fd as isize;
+    }else {
+        return -1;
+    }
+}
+
+// 关闭文件，释放fd
+pub fn sys_close(fd: usize) -> isize {
+    let proc = current_process().unwrap();
+    let mut inner = proc.exclusive_borrow_inner();
+    if fd >= inner.fd_table.len() {
+        return -1;
+    }
+    if inner.fd_table[fd].take().is_none() {
+        return -1;
+    }
+    return 0;
+}
+
+// 创建目录，成功返回0
+pub fn sys_mkdir(path: *const u8) -> isize {
+    let path_str = translate_string(current_proc_satp(), path);
+    if mkdir(&path_str).is_ok() {
+        return 0;
+    }
+    return -1;
+}
+
+// 将old_path移动到new_path，new_path已存在时原子地替换，成功返回0，失败返回错误码的相反数
+// 和sys_open一样使用Linux的系统调用号但不接受目录fd，两个路径都从根目录开始解析
+pub fn sys_rename(old_path: *const u8, new_path: *const u8) -> isize {
+    let satp = current_proc_satp();
+    let old_path = translate_string(satp, old_path);
+    let new_path = translate_string(satp, new_path);
+    return match rename(&old_path, &new_path) {
+        Ok(()) => 0,
+        Err(err) => fs_errno(err),
+    };
+}
+
+// 将fd对应文件被修改的数据写回块设备，成功返回0
+pub fn sys_fsync(fd: usize) -> isize {
+    let proc = current_process().unwrap();
+    let inner = proc.exclusive_borrow_inner();
+    if fd >= inner.fd_table.len() {
+        return -1;
+    }
+    if let Some(file) = &inner.fd_table[fd] {
+        let file = file.clone();
+        // 写回块设备期间可能发生进程切换，先释放PCB的借用
+        drop(inner);
+        file.sync();
+        return 0;
+    }else {
+        return -1;
     }
 }
 
+// 将文件系统所有被修改的数据写回块设备
+pub fn sys_sync() -> isize {
+    sync_all();
+    return 0;
+}
+
+// 获取fd对应文件的状态，写入用户空间的stat，成功返回0
+pub fn sys_fstat(fd: usize, stat: *mut FileStat) -> isize {
+    let proc = current_process().unwrap();
+    let inner = proc.exclusive_borrow_inner();
+    if fd >= inner.fd_table.len() {
+        return -1;
+    }
+    let file = match &inner.fd_table[fd] {
+        Some(file) => file.clone(),
+        None => return -1,
+    };
+    let satp = inner.user_space_satp();
+    drop(inner);
+    // 标准输入输出没有文件状态
+    let file_stat = match file.stat() {
+        Some(file_stat) => file_stat,
+        None => return -1,
+    };
+    let mode = match file_stat._type {
+        INodeType::File => StatMode::FILE,
+        INodeType::Directory => StatMode::DIR,
+        INodeType::Symlink => StatMode::LINK,
+    };
+    *translate_ptr(satp, stat) = FileStat {
+        ino: file_stat.inode,
+        mode: mode.bits() | file_stat.mode,
+        size: file_stat.size,
+        blocks: file_stat.blocks,
+        uid: file_stat.uid,
+        gid: file_stat.gid,
+        atime: file_stat.atime,
+        mtime: file_stat.mtime,
+        ctime: file_stat.ctime,
+        nlink: file_stat.nlink,
+    };
+    return 0;
+}
+
+// 获取文件系统的容量和空闲空间，写入用户空间的statfs，成功返回0
+pub fn sys_statfs(statfs: *mut StatFs) -> isize {
+    let root = match root_inode() {
+        Some(root) => root,
+        None => return -1,
+    };
+    *translate_ptr(current_proc_satp(), statfs) = root.statfs();
+    return 0;
+}
@@ -1,11 +1,21 @@
 // os/src/syscall/mod.rs
 pub mod fs;
 pub mod proc;
-use fs::*;
+use self::fs::*;
 use proc::*;
+use crate::file::FileStat;
+use ::fs::fs::StatFs;
 
+const SYS_CALL_MKDIR: usize = 34;
+const SYS_CALL_RENAME: usize = 38;
+const SYS_CALL_STATFS: usize = 43;
+const SYS_CALL_OPEN: usize = 56;
+const SYS_CALL_CLOSE: usize = 57;
 const SYS_CALL_READ: usize = 63;
 const SYS_CALL_WRITE: usize = 64;
+const SYS_CALL_FSTAT: usize = 80;
+const SYS_CALL_SYNC: usize = 81;
+const SYS_CALL_FSYNC: usize = 82;
 const SYS_CALL_EXIT: usize = 93;
 const SYS_CALL_YIELD: usize = 124;
 const SYS_CALL_GET_TIME: usize = 169;
@@ -18,8 +28,16 @@ const SYS_CALL_SPAWN: usize = 400;
 
 pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
     match syscall_id {
+        SYS_CALL_MKDIR => sys_mkdir(args[0] as *const u8),
+        SYS_CALL_RENAME => sys_rename(args[0] as *const u8, args[1] as *const u8),
+        SYS_CALL_STATFS => sys_statfs(args[0] as *mut StatFs),
+        SYS_CALL_OPEN => sys_open(args[0] as *const u8, args[1] as u32),
+        SYS_CALL_CLOSE => sys_close(args[0]),
         SYS_CALL_READ => sys_read(args[0], args[1] as *mut u8, args[2]),
         SYS_CALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
+        SYS_CALL_FSTAT => sys_fstat(args[0], args[1] as *mut FileStat),
+        SYS_CALL_SYNC => sys_sync(),
+        SYS_CALL_FSYNC => sys_fsync(args[0]),
         SYS_CALL_EXIT => sys_exit(args[0] as i32),
         SYS_CALL_YIELD => sys_yield(),
         SYS_CALL_GET_TIME => sys_get_time(),
@@ -2,7 +2,7 @@ use crate::timer::get_time_ms;
 use crate::proc::{suspend_current_and_run_next, current_process, add_process, current_proc_satp, exit_current_and_run_next};
 use crate::proc::pcb::{ProcessStatus, ProcessControlBlock};
 use crate::mem::page_table::{translate_string, translate_ptr};
-use crate::loader::get_app_data_by_name;
+use crate::loader::get_app_data;
 use alloc::sync::Arc;
 
 
@@ -37,9 +37,9 @@ pub fn sys_fork() -> isize {
 pub fn sys_exec(path: *const u8) -> isize {
     let satp = current_proc_satp();
     let path_str = translate_string(satp, path);
-    if let Some(elf_data) = get_app_data_by_name(&path_str) {
+    if let Some(elf_data) = get_app_data(&path_str) {
         let proc = current_process().unwrap();
-        proc.exec(elf_data);
+        proc.exec(&elf_data);
         return 0;
     }else {
         return -1;
@@ -78,9 +78,9 @@ pub fn sys_waitpid(pid: isize, exit_code: *mut i32) -> isize {
 pub fn sys_spawn(path: *const u8) -> isize {
     let satp = current_proc_satp();
     let str = translate_string(satp, path);
-    if let Some(app_data) = get_app_data_by_name(&str) {
+    if let Some(app_data) = get_app_data(&str) {
         let parent = current_process().unwrap();
-        let child_pcb = Arc::new(ProcessControlBlock::new(app_data));
+        let child_pcb = Arc::new(ProcessControlBlock::new(&app_data));
         let pid = child_pcb.pid.0;
         let mut child_inner = child_pcb.exclusive_borrow_inner();
         child_inner.parent = Some(Arc::downgrade(&parent));
@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate lib_rusty;
+use lib_rusty::*;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let test_str = "Hello, rusty file system!";
+    let path = "file_rw\0";
+    // 创建并清空文件，写入测试数据
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::WRITE);
+    assert!(fd > 0);
+    write(fd as usize, test_str.as_bytes());
+    // 将写入的数据写回磁盘
+    assert_eq!(fsync(fd as usize), 0);
+    // 文件状态中的大小与写入的数据一致
+    let mut stat = Stat::default();
+    assert_eq!(fstat(fd as usize, &mut stat), 0);
+    assert_eq!(stat.mode & StatMode::TYPE_MASK.bits(), StatMode::FILE.bits());
+    assert_eq!(stat.mode & StatMode::PERM_MASK.bits(), 0o644);
+    assert_eq!(stat.size as usize, test_str.len());
+    assert_eq!(stat.blocks, 1);
+    assert_eq!(stat.nlink, 1);
+    close(fd as usize);
+    let mut fs_stat = StatFs::default();
+    assert_eq!(statfs(&mut fs_stat), 0);
+    assert!(fs_stat.free_blocks < fs_stat.total_blocks);
+    assert!(fs_stat.free_inodes < fs_stat.total_inodes);
+
+    // 重新打开文件，读取数据并比较
+    let fd = open(path, OpenFlags::READ);
+    assert!(fd > 0);
+    let mut buffer = [0u8; 100];
+    let len = read(fd as usize, &mut buffer) as usize;
+    close(fd as usize);
+    assert_eq!(test_str, core::str::from_utf8(&buffer[..len]).unwrap());
+
+    // 先写临时文件，再替换原文件
+    let new_str = "Replaced by rename!";
+    let tmp_path = "file_rw.tmp\0";
+    let fd = open(tmp_path, OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::WRITE);
+    assert!(fd > 0);
+    write(fd as usize, new_str.as_bytes());
+    assert_eq!(fsync(fd as usize), 0);
+    close(fd as usize);
+    assert_eq!(rename(tmp_path, path), 0);
+    // 临时文件已经不存在，原文件为新的内容
+    assert!(open(tmp_path, OpenFlags::READ) < 0);
+    let fd = open(path, OpenFlags::READ);
+    assert!(fd > 0);
+    let len = read(fd as usize, &mut buffer) as usize;
+    close(fd as usize);
+    assert_eq!(new_str, core::str::from_utf8(&buffer[..len]).unwrap());
+    println!("Test file_rw OK!");
+    0
+}
@@ -110,6 +110,43 @@ bitflags::bitflags! {
     }
 }
 
+bitflags::bitflags! {
+    // fstat返回的文件类型和权限位
+    pub struct StatMode: u32 {
+        const DIR = 0o040000;
+        const FILE = 0o100000;
+        const LINK = 0o120000;
+        const TYPE_MASK = 0o170000;
+        const PERM_MASK = 0o777;
+    }
+}
+
+// fstat返回的文件状态
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct Stat {
+    pub ino: u32,    // inode编号
+    pub mode: u32,   // 文件类型和rwx权限位，StatMode
+    pub size: u32,   // 文件大小
+    pub blocks: u32, // 占用的数据块和索引块数量
+    pub uid: u32,    // 所有者用户id
+    pub gid: u32,    // 所有者组id
+    pub atime: u32,  // 最后访问时间，单位秒
+    pub mtime: u32,  // 文件内容最后修改时间
+    pub ctime: u32,  // inode最后修改时间
+    pub nlink: u32,  // 链接数
+}
+
+// statfs返回的文件系统容量和空闲空间
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct StatFs {
+    pub block_size: u32,
+    pub total_inodes: u32,
+    pub free_inodes: u32,
+    pub total_blocks: u32,
+    pub free_blocks: u32,
+}
 
 pub fn open(path: &str, flags: OpenFlags) -> isize {
     sys_open(path, flags.bits)
@@ -117,4 +154,29 @@ pub fn open(path: &str, flags: OpenFlags) -> isize {
 
 pub fn close(fd: usize) -> isize {
     sys_close(fd)
-}
\ No newline at end of file
+}
+
+pub fn mkdir(path: &str) -> isize {
+    sys_mkdir(path)
+}
+
+// 将old_path移动到new_path，new_path已存在时替换原有的文件，失败返回错误码的相反数
+pub fn rename(old_path: &str, new_path: &str) -> isize {
+    sys_rename(old_path, new_path)
+}
+
+pub fn sync() -> isize {
+    sys_sync()
+}
+
+pub fn fsync(fd: usize) -> isize {
+    sys_fsync(fd)
+}
+
+pub fn fstat(fd: usize, stat: &mut Stat) -> isize {
+    sys_fstat(fd, stat)
+}
+
+pub fn statfs(statfs: &mut StatFs) -> isize {
+    sys_statfs(statfs)
+}
@@ -1,10 +1,17 @@
 use core::arch::asm;
+use crate::{Stat, StatFs};
 
+const SYSCALL_MKDIR: usize = 34;
+const SYSCALL_RENAME: usize = 38;
+const SYSCALL_STATFS: usize = 43;
 const SYSCALL_OPEN: usize = 56;
 const SYSCALL_CLOSE: usize = 57;
 
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_FSTAT: usize = 80;
+const SYSCALL_SYNC: usize = 81;
+const SYSCALL_FSYNC: usize = 82;
 const SYSCALL_EXIT: usize = 93;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_GET_TIME: usize = 169;
@@ -71,4 +78,28 @@ pub fn sys_open(path: &str, flags: u32) -> isize {
 
 pub fn sys_close(fd: usize) -> isize {
     syscall(SYSCALL_CLOSE, [fd, 0, 0])
-}
\ No newline at end of file
+}
+
+pub fn sys_mkdir(path: &str) -> isize {
+    syscall(SYSCALL_MKDIR, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_rename(old_path: &str, new_path: &str) -> isize {
+    syscall(SYSCALL_RENAME, [old_path.as_ptr() as usize, new_path.as_ptr() as usize, 0])
+}
+
+pub fn sys_sync() -> isize {
+    syscall(SYSCALL_SYNC, [0, 0, 0])
+}
+
+pub fn sys_fsync(fd: usize) -> isize {
+    syscall(SYSCALL_FSYNC, [fd, 0, 0])
+}
+
+pub fn sys_fstat(fd: usize, stat: &mut Stat) -> isize {
+    syscall(SYSCALL_FSTAT, [fd, stat as *mut Stat as usize, 0])
+}
+
+pub fn sys_statfs(statfs: &mut StatFs) -> isize {
+    syscall(SYSCALL_STATFS, [statfs as *mut StatFs as usize, 0, 0])
+}